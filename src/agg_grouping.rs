@@ -25,11 +25,13 @@ impl AggGrouping {
 
         let mut agg_field = Option::None;
         for proj_obj in &projection.items {
-            if matches!(&proj_obj, ColRef::AggregateRef { func: _, source: _ }) {
-                if agg_field.is_none() {
-                    agg_field = Option::Some(proj_obj.clone());
-                } else if agg_field.as_ref().unwrap() != proj_obj {
-                    panic!("Multiple different aggregations present!")
+            for agg in proj_obj.aggregates() {
+                match &agg_field {
+                    Option::None => agg_field = Option::Some(agg),
+                    Option::Some(existing) if existing != &agg => {
+                        panic!("Multiple different aggregations present!")
+                    }
+                    _ => {}
                 }
             }
         }
@@ -58,9 +60,9 @@ impl AggGrouping {
             input: op,
             grouping: self.groups.clone(),
             agg_field: self.agg_field.clone().unwrap(),
+            combines_partials: false,
             ls: Option::None,
             vs,
-            cfg_name: Option::None,
         }))
     }
 }
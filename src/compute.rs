@@ -0,0 +1,75 @@
+use crate::ops::*;
+use crate::projection::*;
+use crate::selection::Selection;
+
+#[derive(Debug)]
+pub struct Compute {
+    exprs: Vec<ColRef>,
+}
+
+fn _push_computed(exprs: &mut Vec<ColRef>, colref: &ColRef) {
+    if matches!(colref, ColRef::ComputedRef { expr: _ }) && !exprs.contains(colref) {
+        exprs.push(colref.clone());
+    }
+}
+
+impl Compute {
+    /**
+     * Expressions that `WHERE` filters on, computed over the joined tables.
+     */
+    pub fn pre_selection(selection: &Selection) -> Compute {
+        let mut exprs = Vec::new();
+
+        for field in selection.computed_fields() {
+            _push_computed(&mut exprs, &field);
+        }
+
+        Compute { exprs }
+    }
+
+    /**
+     * Expressions that feed an aggregate, e.g. the `l.PRICE * 0.9` in `SUM(l.PRICE * 0.9)`.
+     */
+    pub fn pre_aggregation(projection: &Projection) -> Compute {
+        let mut exprs = Vec::new();
+
+        for item in &projection.items {
+            for agg in item.aggregates() {
+                if let ColRef::AggregateRef { func: _, source } = &agg {
+                    _push_computed(&mut exprs, source);
+                }
+            }
+        }
+
+        Compute { exprs }
+    }
+
+    /**
+     * Expressions in the output itself, computed over the (possibly aggregated) input.
+     */
+    pub fn post_aggregation(projection: &Projection) -> Compute {
+        let mut exprs = Vec::new();
+
+        for item in &projection.items {
+            _push_computed(&mut exprs, item);
+        }
+
+        Compute { exprs }
+    }
+
+    pub fn apply_compute_ops(&self, op: Op) -> Op {
+        if self.exprs.is_empty() {
+            return op;
+        }
+
+        let mut vs = op.virtual_schema();
+        vs.columns.extend(self.exprs.iter().cloned());
+
+        Op::ComputeOp(Box::new(OpCompute {
+            input: op,
+            exprs: self.exprs.clone(),
+            ls: Option::None,
+            vs,
+        }))
+    }
+}
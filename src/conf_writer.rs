@@ -2,6 +2,8 @@ use std::collections::HashMap;
 
 use crate::metadata::{MetaType, Metadata};
 use crate::ops::*;
+use crate::physical::*;
+use crate::planner::LocalSchema;
 
 use json::{object, JsonValue};
 
@@ -28,7 +30,7 @@ impl ConfigNamespace {
     }
 }
 
-pub fn plan_to_json(mut plan: Op, meta: &Metadata) -> String {
+pub fn plan_to_json(mut plan: PhysicalOp, meta: &Metadata) -> String {
     let mut namespace = ConfigNamespace::new();
     plan.name_op(&mut namespace);
 
@@ -45,7 +47,7 @@ pub fn plan_to_json(mut plan: Op, meta: &Metadata) -> String {
     data.dump()
 }
 
-impl OpScan {
+impl PhysScan {
     fn name_op(&mut self, namespace: &mut ConfigNamespace) {
         if self.cfg_name.is_none() {
             self.cfg_name =
@@ -76,13 +78,39 @@ impl OpScan {
     }
 }
 
-impl OpJoin {
+/**
+ * Maps each output column of a join to its build (`B$i`) or probe (`P$i`) input column.
+ */
+fn _join_projection(build: &PhysicalOp, probe: &PhysicalOp, ls: &LocalSchema) -> Vec<String> {
+    let buildcols = &build.local_schema().columns;
+    let probecols = &probe.local_schema().columns;
+
+    let mut projection = Vec::<String>::new();
+    for colref in &ls.columns {
+        for (i, item) in buildcols.iter().enumerate() {
+            if item == colref {
+                projection.push(format!("B${}", i));
+            }
+        }
+        for (i, item) in probecols.iter().enumerate() {
+            if item == colref {
+                projection.push(format!("P${}", i));
+            }
+        }
+    }
+    projection
+}
+
+impl PhysJoin {
     fn name_op(&mut self, namespace: &mut ConfigNamespace) {
         self.build.name_op(namespace);
         self.probe.name_op(namespace);
 
         if self.cfg_name.is_none() {
-            self.cfg_name = Option::Some(namespace.name_operator("join"));
+            self.cfg_name = Option::Some(namespace.name_operator(match self.algorithm {
+                JoinAlgorithm::NestedLoop => "crossjoin",
+                JoinAlgorithm::Hash | JoinAlgorithm::SortMerge => "join",
+            }));
         }
     }
 
@@ -90,47 +118,59 @@ impl OpJoin {
         self.build.preflight(global);
         self.probe.preflight(global);
 
-        let buildcols = self.build.local_schema().unwrap().columns;
-        let probecols = self.probe.local_schema().unwrap().columns;
-
-        let mut projection = Vec::<String>::new();
-        for colref in &self.ls.as_ref().unwrap().columns {
-            for (i, item) in buildcols.iter().enumerate() {
-                if item == colref {
-                    projection.push(format!("B${}", i));
-                }
-            }
-            for (i, item) in probecols.iter().enumerate() {
-                if item == colref {
-                    projection.push(format!("P${}", i));
-                }
-            }
-        }
-
-        let buildjattr = self
-            .build
-            .local_schema()
-            .unwrap()
-            .get_field_idx(&self.build_join_attribute);
-        let probejattr = self
-            .probe
-            .local_schema()
-            .unwrap()
-            .get_field_idx(&self.probe_join_attribute);
-
-        global[self.cfg_name.as_ref().unwrap()] = object! {
-            type: "hashjoin",
-            buildjattr: buildjattr,
-            probejattr: probejattr,
-            hash: {
-                fn: "modulo",
-                buckets: 10000,
+        let projection = _join_projection(&self.build, &self.probe, &self.ls);
+
+        let cfg_name = self.cfg_name.as_ref().unwrap();
+        global[cfg_name] = match self.algorithm {
+            JoinAlgorithm::Hash => object! {
+                type: "hashjoin",
+                hash: {
+                    fn: "modulo",
+                    buckets: 10000,
+                },
+                tuplesperbucket: 4,
+                projection_tuple: projection,
+                threadgroups_tuple: [[0]],
+                allocpolicy: "striped",
+            },
+            JoinAlgorithm::SortMerge => object! {
+                type: "mergejoin",
+                projection_tuple: projection,
+                threadgroups_tuple: [[0]],
+                allocpolicy: "striped",
             },
-            tuplesperbucket: 4,
-            projection_tuple: projection,
-            threadgroups_tuple: [[0]],
-            allocpolicy: "striped",
+            JoinAlgorithm::NestedLoop => {
+                global[cfg_name] = object! {
+                    type: "crossjoin",
+                    projection_tuple: projection,
+                    threadgroups_tuple: [[0]],
+                    allocpolicy: "striped",
+                };
+                return;
+            }
         };
+
+        let build_ls = self.build.local_schema();
+        let probe_ls = self.probe.local_schema();
+        let buildjattr: Vec<u32> = self
+            .build_join_attributes
+            .iter()
+            .map(|c| build_ls.get_field_idx(c))
+            .collect();
+        let probejattr: Vec<u32> = self
+            .probe_join_attributes
+            .iter()
+            .map(|c| probe_ls.get_field_idx(c))
+            .collect();
+
+        // Composite keys are lists, a single key stays a plain field
+        if buildjattr.len() == 1 {
+            global[cfg_name]["buildjattr"] = buildjattr[0].into();
+            global[cfg_name]["probejattr"] = probejattr[0].into();
+        } else {
+            global[cfg_name]["buildjattr_tuple"] = buildjattr.into();
+            global[cfg_name]["probejattr_tuple"] = probejattr.into();
+        }
     }
 
     fn node(&self) -> JsonValue {
@@ -142,7 +182,7 @@ impl OpJoin {
     }
 }
 
-impl OpFilter {
+impl PhysFilter {
     fn name_op(&mut self, namespace: &mut ConfigNamespace) {
         self.input.name_op(namespace);
 
@@ -154,13 +194,44 @@ impl OpFilter {
     fn preflight(&self, global: &mut object::Object) {
         self.input.preflight(global);
 
-        let field = self.ls.as_ref().unwrap().get_field_idx(&self.field);
+        let field = self.ls.get_field_idx(&self.field);
 
-        global[self.cfg_name.as_ref().unwrap()] = object! {
-            type: "filter",
-            op: self.op.clone(),
-            field: field,
-            value: self.value.clone(),
+        global[self.cfg_name.as_ref().unwrap()] = match &self.predicate {
+            FilterPredicate::Compare { op, value } => object! {
+                type: "filter",
+                op: op.clone(),
+                field: field,
+                value: value.raw(),
+            },
+            FilterPredicate::Match {
+                mode,
+                pattern,
+                negated,
+                case_insensitive,
+            } => object! {
+                type: "filter",
+                op: match mode {
+                    MatchMode::Prefix => "prefix",
+                    MatchMode::Contains => "contains",
+                    MatchMode::Regex => "regex",
+                },
+                field: field,
+                value: pattern.clone(),
+                negate: *negated,
+                ignorecase: *case_insensitive,
+            },
+            FilterPredicate::CompareColumns { op, other } => object! {
+                type: "filter",
+                op: op.clone(),
+                field: field,
+                otherfield: self.ls.get_field_idx(other),
+            },
+            FilterPredicate::IsNull { negated } => object! {
+                type: "filter",
+                op: "isnull",
+                field: field,
+                negate: *negated,
+            },
         };
     }
 
@@ -172,7 +243,7 @@ impl OpFilter {
     }
 }
 
-impl OpProjection {
+impl PhysProjection {
     fn name_op(&mut self, namespace: &mut ConfigNamespace) {
         self.input.name_op(namespace);
 
@@ -184,9 +255,11 @@ impl OpProjection {
     fn preflight(&self, global: &mut object::Object) {
         self.input.preflight(global);
 
+        let input_ls = self.input.local_schema();
+
         let mut projection = Vec::<String>::new();
         for col in &self.projection {
-            for (idx, vcol) in self.vs.columns.iter().enumerate() {
+            for (idx, vcol) in input_ls.columns.iter().enumerate() {
                 if col == vcol {
                     projection.push(format!("${}", idx));
                     break;
@@ -208,7 +281,7 @@ impl OpProjection {
     }
 }
 
-impl OpAggGroup {
+impl PhysAggregate {
     fn name_op(&mut self, namespace: &mut ConfigNamespace) {
         self.input.name_op(namespace);
 
@@ -222,13 +295,15 @@ impl OpAggGroup {
 
         let mut fields = Vec::new();
         for group_field in &self.grouping {
-            fields.push(self.ls.as_ref().unwrap().get_field_idx(group_field));
+            fields.push(self.ls.get_field_idx(group_field));
         }
 
-        let sumfield_idx = self.ls.as_ref().unwrap().get_field_idx(&self.agg_field);
+        let sumfield_idx = self.ls.get_field_idx(&self.agg_field);
         let hashfield_idx = fields[0];
 
+        // Partial counts and sums are both combined by summing them
         let func = match self.agg_field {
+            _ if self.combines_partials => AggFunc::Sum,
             ColRef::AggregateRef { func, source: _ } => func,
             _ => unreachable!(),
         };
@@ -268,32 +343,101 @@ impl OpAggGroup {
     }
 }
 
-impl OpSortLimit {
+fn _order_fields(
+    order_columns: &[OrderColumn],
+    ls: &LocalSchema,
+) -> (Vec<String>, Vec<u32>, Vec<u32>) {
+    let mut by = Vec::new();
+    let mut asc = Vec::new();
+    let mut nulls_first = Vec::new();
+    for col in order_columns {
+        by.push(format!("${}", ls.get_field_idx(&col.column)));
+        asc.push(if col.order == Order::ASC { 1 } else { 0 });
+        nulls_first.push(if col.nulls_first { 1 } else { 0 });
+    }
+    (by, asc, nulls_first)
+}
+
+impl PhysSort {
     fn name_op(&mut self, namespace: &mut ConfigNamespace) {
         self.input.name_op(namespace);
 
         if self.cfg_name.is_none() {
-            self.cfg_name = Option::Some(namespace.name_operator("sortlimit"));
+            self.cfg_name = Option::Some(namespace.name_operator("sort"));
+        }
+    }
+
+    fn preflight(&self, global: &mut object::Object) {
+        self.input.preflight(global);
+
+        let (by, asc, nulls_first) = _order_fields(&self.order_columns, &self.ls);
+
+        global[self.cfg_name.as_ref().unwrap()] = object! {
+            type: "sort",
+            by: by,
+            asc_tuple: asc,
+            nullsfirst_tuple: nulls_first,
+        };
+    }
+
+    fn node(&self) -> JsonValue {
+        object! {
+            name: self.cfg_name.as_ref().unwrap().to_string(),
+            input: self.input.node(),
+        }
+    }
+}
+
+impl PhysLimit {
+    fn name_op(&mut self, namespace: &mut ConfigNamespace) {
+        self.input.name_op(namespace);
+
+        if self.cfg_name.is_none() {
+            self.cfg_name = Option::Some(namespace.name_operator("limit"));
         }
     }
 
     fn preflight(&self, global: &mut object::Object) {
         self.input.preflight(global);
 
-        let mut order_columns = Vec::new();
-        for col in &self.order_columns {
-            order_columns.push(format!("${}", self.ls.as_ref().unwrap().get_field_idx(col)));
+        let mut cfg = object! {
+            type: "limit",
+            offset: self.offset,
+        };
+        if let Some(limit) = self.limit {
+            cfg["limit"] = limit.into();
+        }
+
+        global[self.cfg_name.as_ref().unwrap()] = cfg;
+    }
+
+    fn node(&self) -> JsonValue {
+        object! {
+            name: self.cfg_name.as_ref().unwrap().to_string(),
+            input: self.input.node(),
         }
+    }
+}
 
-        let mut asc = 1;
-        if self.order.is_some() && self.order.as_ref().unwrap() == &Order::DESC {
-            asc = 0;
+impl PhysTopN {
+    fn name_op(&mut self, namespace: &mut ConfigNamespace) {
+        self.input.name_op(namespace);
+
+        if self.cfg_name.is_none() {
+            self.cfg_name = Option::Some(namespace.name_operator("sortlimit"));
         }
+    }
+
+    fn preflight(&self, global: &mut object::Object) {
+        self.input.preflight(global);
+
+        let (by, asc, nulls_first) = _order_fields(&self.order_columns, &self.ls);
 
         global[self.cfg_name.as_ref().unwrap()] = object! {
             type: "sortlimit",
-            by: order_columns,
-            asc_tuple: [asc],
+            by: by,
+            asc_tuple: asc,
+            nullsfirst_tuple: nulls_first,
             limit_tuple: [self.limit]
         };
     }
@@ -306,7 +450,135 @@ impl OpSortLimit {
     }
 }
 
-impl OpSubqueryProj {
+impl PhysCompute {
+    fn name_op(&mut self, namespace: &mut ConfigNamespace) {
+        self.input.name_op(namespace);
+
+        if self.cfg_name.is_none() {
+            self.cfg_name = Option::Some(namespace.name_operator("compute"));
+        }
+    }
+
+    fn preflight(&self, global: &mut object::Object) {
+        self.input.preflight(global);
+
+        let input_ls = self.input.local_schema();
+
+        let mut expressions = Vec::new();
+        for col in &self.exprs {
+            match col {
+                ColRef::ComputedRef { expr } => expressions.push(expr.preflight_str(input_ls)),
+                _ => unreachable!(),
+            }
+        }
+
+        global[self.cfg_name.as_ref().unwrap()] = object! {
+            type: "compute",
+            expressions: expressions,
+        };
+    }
+
+    fn node(&self) -> JsonValue {
+        object! {
+            name: self.cfg_name.as_ref().unwrap().to_string(),
+            input: self.input.node(),
+        }
+    }
+}
+
+impl PhysEmpty {
+    fn name_op(&mut self, namespace: &mut ConfigNamespace) {
+        if self.cfg_name.is_none() {
+            self.cfg_name = Option::Some(namespace.name_operator("empty"));
+        }
+    }
+
+    fn preflight(&self, global: &mut object::Object) {
+        let schema = JsonValue::Array(
+            self.schema
+                .iter()
+                .map(|c| JsonValue::String(c.preflight_str()))
+                .collect(),
+        );
+
+        global[self.cfg_name.as_ref().unwrap()] = object! {
+            type: "empty",
+            schema: schema
+        };
+    }
+
+    fn node(&self) -> JsonValue {
+        object! {
+            name: self.cfg_name.as_ref().unwrap().to_string(),
+        }
+    }
+}
+
+impl ScalarExpr {
+    fn preflight_str(&self, ls: &LocalSchema) -> String {
+        match self {
+            ScalarExpr::Column(colref) => format!("${}", ls.get_field_idx(colref)),
+            ScalarExpr::Literal(value) => value.to_string(),
+            ScalarExpr::Arith { op, left, right } => format!(
+                "({} {} {})",
+                left.preflight_str(ls),
+                op.symbol(),
+                right.preflight_str(ls)
+            ),
+            ScalarExpr::Case {
+                branches,
+                else_result,
+            } => {
+                let mut out = "(CASE".to_string();
+                for (condition, result) in branches {
+                    out += &format!(
+                        " WHEN {} THEN {}",
+                        condition.preflight_str(ls),
+                        result.preflight_str(ls)
+                    );
+                }
+                if let Some(else_result) = else_result {
+                    out += &format!(" ELSE {}", else_result.preflight_str(ls));
+                }
+                out + " END)"
+            }
+            ScalarExpr::Cast { expr, to } => {
+                format!("CAST({} AS {})", expr.preflight_str(ls), to.preflight_str())
+            }
+            ScalarExpr::Null => "NULL".to_string(),
+        }
+    }
+}
+
+impl ScalarCondition {
+    fn preflight_str(&self, ls: &LocalSchema) -> String {
+        let join = |conditions: &[ScalarCondition], separator: &str| {
+            conditions
+                .iter()
+                .map(|c| c.preflight_str(ls))
+                .collect::<Vec<String>>()
+                .join(separator)
+        };
+
+        match self {
+            ScalarCondition::Compare { op, left, right } => format!(
+                "({} {} {})",
+                left.preflight_str(ls),
+                op,
+                right.preflight_str(ls)
+            ),
+            ScalarCondition::IsNull { expr, negated } => format!(
+                "({} IS {}NULL)",
+                expr.preflight_str(ls),
+                if *negated { "NOT " } else { "" }
+            ),
+            ScalarCondition::And(conditions) => format!("({})", join(conditions, " AND ")),
+            ScalarCondition::Or(conditions) => format!("({})", join(conditions, " OR ")),
+        }
+    }
+}
+
+impl PhysRename {
     fn name_op(&mut self, namespace: &mut ConfigNamespace) {
         self.input.name_op(namespace);
     }
@@ -320,40 +592,52 @@ impl OpSubqueryProj {
     }
 }
 
-impl Op {
+impl PhysicalOp {
     fn name_op(&mut self, namespace: &mut ConfigNamespace) {
         match self {
-            Op::ScanOp(op) => op.name_op(namespace),
-            Op::JoinOp(op) => op.name_op(namespace),
-            Op::FilterOp(op) => op.name_op(namespace),
-            Op::ProjectionOp(op) => op.name_op(namespace),
-            Op::AggGroupOp(op) => op.name_op(namespace),
-            Op::SortLimitOp(op) => op.name_op(namespace),
-            Op::SubqueryProjOp(op) => op.name_op(namespace),
+            PhysicalOp::ScanOp(op) => op.name_op(namespace),
+            PhysicalOp::JoinOp(op) => op.name_op(namespace),
+            PhysicalOp::FilterOp(op) => op.name_op(namespace),
+            PhysicalOp::ProjectionOp(op) => op.name_op(namespace),
+            PhysicalOp::AggregateOp(op) => op.name_op(namespace),
+            PhysicalOp::SortOp(op) => op.name_op(namespace),
+            PhysicalOp::TopNOp(op) => op.name_op(namespace),
+            PhysicalOp::LimitOp(op) => op.name_op(namespace),
+            PhysicalOp::ComputeOp(op) => op.name_op(namespace),
+            PhysicalOp::EmptyOp(op) => op.name_op(namespace),
+            PhysicalOp::RenameOp(op) => op.name_op(namespace),
         }
     }
 
     fn preflight(&self, global: &mut object::Object) {
         match self {
-            Op::ScanOp(op) => op.preflight(global),
-            Op::JoinOp(op) => op.preflight(global),
-            Op::FilterOp(op) => op.preflight(global),
-            Op::ProjectionOp(op) => op.preflight(global),
-            Op::AggGroupOp(op) => op.preflight(global),
-            Op::SortLimitOp(op) => op.preflight(global),
-            Op::SubqueryProjOp(op) => op.preflight(global),
+            PhysicalOp::ScanOp(op) => op.preflight(global),
+            PhysicalOp::JoinOp(op) => op.preflight(global),
+            PhysicalOp::FilterOp(op) => op.preflight(global),
+            PhysicalOp::ProjectionOp(op) => op.preflight(global),
+            PhysicalOp::AggregateOp(op) => op.preflight(global),
+            PhysicalOp::SortOp(op) => op.preflight(global),
+            PhysicalOp::TopNOp(op) => op.preflight(global),
+            PhysicalOp::LimitOp(op) => op.preflight(global),
+            PhysicalOp::ComputeOp(op) => op.preflight(global),
+            PhysicalOp::EmptyOp(op) => op.preflight(global),
+            PhysicalOp::RenameOp(op) => op.preflight(global),
         }
     }
 
     fn node(&self) -> JsonValue {
         match self {
-            Op::ScanOp(op) => op.node(),
-            Op::JoinOp(op) => op.node(),
-            Op::FilterOp(op) => op.node(),
-            Op::ProjectionOp(op) => op.node(),
-            Op::AggGroupOp(op) => op.node(),
-            Op::SortLimitOp(op) => op.node(),
-            Op::SubqueryProjOp(op) => op.node(),
+            PhysicalOp::ScanOp(op) => op.node(),
+            PhysicalOp::JoinOp(op) => op.node(),
+            PhysicalOp::FilterOp(op) => op.node(),
+            PhysicalOp::ProjectionOp(op) => op.node(),
+            PhysicalOp::AggregateOp(op) => op.node(),
+            PhysicalOp::SortOp(op) => op.node(),
+            PhysicalOp::TopNOp(op) => op.node(),
+            PhysicalOp::LimitOp(op) => op.node(),
+            PhysicalOp::ComputeOp(op) => op.node(),
+            PhysicalOp::EmptyOp(op) => op.node(),
+            PhysicalOp::RenameOp(op) => op.node(),
         }
     }
 }
@@ -361,9 +645,11 @@ impl Op {
 impl MetaType {
     fn preflight_str(&self) -> String {
         match self {
-            MetaType::LONG => "long",
-            MetaType::DEC => "dec",
+            MetaType::LONG => "long".to_string(),
+            MetaType::DEC => "dec".to_string(),
+            MetaType::INT => "int".to_string(),
+            MetaType::DATE => "date".to_string(),
+            MetaType::CHAR(n) | MetaType::VARCHAR(n) => format!("char:{}", n),
         }
-        .to_string()
     }
 }
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+
+use crate::metadata::Metadata;
+
+/**
+ * Weights of the work each pythia operator does. Costs are in arbitrary units, only their
+ * ratios matter. Any field left out of a config file keeps its default, unknown fields are
+ * rejected so a misspelled one doesn't silently fall back to it.
+ */
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CostModel {
+    /**
+     * Per byte a scan reads.
+     */
+    pub scan_byte: f64,
+    /**
+     * Per tuple passed through a filter, projection, compute or limit.
+     */
+    pub tuple: f64,
+    /**
+     * Per tuple inserted into a hash table, by a hash join build side or an aggregation.
+     */
+    pub hash_build: f64,
+    /**
+     * Per tuple looked up in a hash table.
+     */
+    pub hash_probe: f64,
+    /**
+     * Per input tuple of a sort-merge join.
+     */
+    pub merge_tuple: f64,
+    /**
+     * Per comparison of a sort or top-N, `n log n` comparisons to sort `n` tuples.
+     */
+    pub sort_comparison: f64,
+    /**
+     * Per pair of tuples a nested-loop join compares.
+     */
+    pub nested_loop_pair: f64,
+    /**
+     * Bytes a hash table or top-N heap may take up, `Metadata::buffsize` if unset.
+     */
+    pub memory_budget: Option<u64>,
+}
+
+impl Default for CostModel {
+    fn default() -> CostModel {
+        CostModel {
+            scan_byte: 0.1,
+            tuple: 1.0,
+            hash_build: 2.0,
+            hash_probe: 1.0,
+            merge_tuple: 1.0,
+            sort_comparison: 1.0,
+            nested_loop_pair: 1.0,
+            memory_budget: Option::None,
+        }
+    }
+}
+
+impl CostModel {
+    pub fn from_path(path: &str) -> CostModel {
+        use std::fs::File;
+        use std::io::Read;
+
+        let mut file = File::open(path).unwrap();
+        let mut data = String::new();
+        file.read_to_string(&mut data).unwrap();
+
+        serde_json::from_str(&data).expect("Failed to parse cost model file!")
+    }
+
+    pub fn memory_budget(&self, meta: &Metadata) -> u64 {
+        self.memory_budget.unwrap_or(meta.buffsize)
+    }
+
+    pub fn scan(&self, rows: f64, width: u64) -> f64 {
+        rows * width as f64 * self.scan_byte
+    }
+
+    pub fn pass_through(&self, rows: f64) -> f64 {
+        rows * self.tuple
+    }
+
+    pub fn hash_join(&self, build_rows: f64, probe_rows: f64) -> f64 {
+        build_rows * self.hash_build + probe_rows * self.hash_probe
+    }
+
+    pub fn merge_join(&self, build_rows: f64, probe_rows: f64) -> f64 {
+        (build_rows + probe_rows) * self.merge_tuple
+    }
+
+    pub fn nested_loop_join(&self, build_rows: f64, probe_rows: f64) -> f64 {
+        build_rows * probe_rows * self.nested_loop_pair
+    }
+
+    pub fn aggregate(&self, rows: f64) -> f64 {
+        rows * self.hash_build
+    }
+
+    pub fn sort(&self, rows: f64) -> f64 {
+        rows * rows.max(2.0).log2() * self.sort_comparison
+    }
+
+    /**
+     * A top-N only compares against a heap of `kept` tuples.
+     */
+    pub fn top_n(&self, rows: f64, kept: u64) -> f64 {
+        rows * (kept.max(2) as f64).log2() * self.sort_comparison
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_defaults_of_missing_fields() {
+        let model: CostModel = serde_json::from_str(r#"{"tuple": 2.5, "memory_budget": 4096}"#)
+            .expect("Failed to parse cost model");
+        assert_eq!(model.tuple, 2.5);
+        assert_eq!(model.memory_budget, Option::Some(4096));
+        assert_eq!(model.hash_build, CostModel::default().hash_build);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let err = serde_json::from_str::<CostModel>(r#"{"hash_bulid": 3.0}"#).unwrap_err();
+        assert!(err.to_string().contains("unknown field `hash_bulid`"));
+    }
+}
@@ -5,18 +5,32 @@ use sqlparser::parser::Parser;
 use clap::{App, Arg};
 
 mod agg_grouping;
+mod compute;
 mod conf_writer;
+mod cost;
+mod memo;
 mod metadata;
 mod ops;
 mod optimizer;
+mod physical;
 mod planner;
 mod projection;
+mod resolve;
+mod rules;
 mod selection;
 mod sort_limit;
+mod stats;
+#[cfg(test)]
+mod test_util;
+mod trace;
+mod typecheck;
 
 use conf_writer::plan_to_json;
+use cost::CostModel;
 use metadata::*;
 use planner::*;
+use rules::RuleSet;
+use trace::Trace;
 
 fn main() {
     let matches = App::new("RPSQL Query Planner")
@@ -32,6 +46,28 @@ fn main() {
                 .long("explain")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name("disable_rule")
+                .help("Turns off an optimizer rule, may be given several times")
+                .long("disable-rule")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .possible_values(&RuleSet::names()),
+        )
+        .arg(
+            Arg::with_name("cost_file")
+                .help("Reads the cost model parameters from a json file")
+                .long("cost-model")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("trace_optimizer")
+                .help("Logs every optimizer step to stderr")
+                .long("trace-optimizer")
+                .takes_value(true)
+                .possible_values(&["text", "json"]),
+        )
         .arg(
             Arg::with_name("meta_file")
                 .help("Reads the metadata from a json file")
@@ -48,6 +84,12 @@ fn main() {
         Metadata::from_default()
     };
 
+    let cost_model = if let Some(cost_path) = matches.value_of("cost_file") {
+        CostModel::from_path(cost_path)
+    } else {
+        CostModel::default()
+    };
+
     let dialect = GenericDialect {};
 
     let ast = Parser::parse_sql(&dialect, sql).unwrap().remove(0);
@@ -57,10 +99,34 @@ fn main() {
         _ => panic!("Not a query"),
     };
 
-    let (exec_plan, _col_names) = plan(&query, &meta);
+    let disabled_rules: Vec<&str> = matches
+        .values_of("disable_rule")
+        .map(|values| values.collect())
+        .unwrap_or_default();
+    let rules = RuleSet::without(&disabled_rules);
+
+    let trace_format = matches.value_of("trace_optimizer");
+    let mut trace = Trace::new(trace_format.is_some(), &meta);
+
+    let (logical_plan, _col_names) = plan(&query, &meta, &rules, &mut trace);
+
+    let estimated_rows = stats::estimate_rows(&logical_plan, &meta);
+    let has_cross_join = logical_plan.contains_cross_join();
+    let exec_plan = memo::optimize(logical_plan, &meta, &cost_model, &mut trace);
+
+    // The trace goes to stderr so the plan on stdout stays usable
+    match trace_format {
+        Some("json") => eprintln!("{}", trace.to_json().pretty(2)),
+        Some(_) => eprint!("{}", trace),
+        None => {}
+    }
 
     if matches.is_present("explain") {
         println!("{}", exec_plan);
+        if has_cross_join {
+            println!("Warning: the plan contains a cross product");
+        }
+        println!("Estimated rows: {:.0}", estimated_rows);
         return;
     }
 
@@ -0,0 +1,724 @@
+use std::collections::HashMap;
+
+use crate::cost::CostModel;
+use crate::metadata::Metadata;
+use crate::ops::*;
+use crate::physical::*;
+use crate::planner::{LocalSchema, VirtualSchema};
+use crate::stats;
+use crate::trace::{Trace, TraceStep};
+
+type GroupId = usize;
+
+/**
+ * A logical op whose inputs are groups. `op` keeps the op trees it was built from as its
+ * inputs, only its own fields are read.
+ */
+#[derive(Debug, Clone)]
+struct MemoExpr {
+    op: Op,
+    inputs: Vec<GroupId>,
+}
+
+/**
+ * Logical expressions that all produce the same tuples.
+ */
+#[derive(Debug)]
+struct Group {
+    exprs: Vec<MemoExpr>,
+    /**
+     * The first expression as a whole op tree, which provides the schemas and row estimate.
+     */
+    repr: Op,
+    rows: f64,
+    /**
+     * Groups the join tree below this group combines, sorted. Anything but a join is its own
+     * only leaf.
+     */
+    leaves: Vec<GroupId>,
+    /**
+     * Index into `Memo::regions` for groups of a join tree.
+     */
+    region: Option<usize>,
+}
+
+/**
+ * Cascades-style search: the logical plan is copied into groups of equivalent expressions,
+ * transformation rules add join orders, and implementation rules cost every way to execute
+ * each expression. The cheapest physical plan is extracted per group and required sort order.
+ */
+struct Memo<'a, 't> {
+    groups: Vec<Group>,
+    /**
+     * For each join tree, the columns any join in it may have to produce: what the tree outputs
+     * plus all of its join keys.
+     */
+    regions: Vec<Vec<ColRef>>,
+    joins: HashMap<Vec<GroupId>, GroupId>,
+    winners: HashMap<(GroupId, Vec<ColRef>), PhysicalOp>,
+    meta: &'a Metadata,
+    model: &'a CostModel,
+    trace: &'a mut Trace<'t>,
+}
+
+/**
+ * Chooses the cheapest physical plan for a logical plan, exploring join orders and operator
+ * implementations. Expects local schemas to be assigned already.
+ */
+pub fn optimize(op: Op, meta: &Metadata, model: &CostModel, trace: &mut Trace) -> PhysicalOp {
+    let mut memo = Memo {
+        groups: Vec::new(),
+        regions: Vec::new(),
+        joins: HashMap::new(),
+        winners: HashMap::new(),
+        meta,
+        model,
+        trace,
+    };
+
+    let root = memo.insert(op, Option::None);
+    memo.explore();
+    memo.best(root, &[])
+}
+
+fn _join_tree_columns(op: &Op, columns: &mut Vec<ColRef>) {
+    if let Op::JoinOp(op_join) = op {
+        for key in op_join
+            .build_join_attributes
+            .iter()
+            .chain(&op_join.probe_join_attributes)
+        {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+        _join_tree_columns(&op_join.build, columns);
+        _join_tree_columns(&op_join.probe, columns);
+    }
+}
+
+fn _estimate(rows: f64, cost: f64, inputs: &[&PhysicalOp]) -> OpCost {
+    OpCost {
+        rows,
+        cost,
+        total: cost + inputs.iter().map(|i| i.estimate().total).sum::<f64>(),
+    }
+}
+
+/**
+ * Where the sorted columns of an input line up with the join keys, the position of each of the
+ * leading sorted columns among the keys.
+ */
+fn _sorted_key_positions(order: &[ColRef], keys: &[ColRef]) -> Option<Vec<usize>> {
+    if order.len() < keys.len() {
+        return Option::None;
+    }
+    order[..keys.len()]
+        .iter()
+        .map(|c| keys.iter().position(|k| k == c))
+        .collect()
+}
+
+fn _sort_on(op: PhysicalOp, keys: &[ColRef], model: &CostModel) -> PhysicalOp {
+    let ls = op.local_schema().clone();
+    let rows = op.estimate().rows;
+    PhysicalOp::SortOp(Box::new(PhysSort {
+        estimate: _estimate(rows, model.sort(rows), &[&op]),
+        input: op,
+        order_columns: keys
+            .iter()
+            .map(|k| OrderColumn {
+                column: k.clone(),
+                order: Order::ASC,
+                nulls_first: Order::ASC.default_nulls_first(),
+            })
+            .collect(),
+        ls,
+        cfg_name: Option::None,
+    }))
+}
+
+/**
+ * Implements an op that has a single physical counterpart on top of its implemented inputs.
+ */
+fn _implement(op: &Op, inputs: Vec<PhysicalOp>, estimate: OpCost) -> PhysicalOp {
+    let mut inputs = inputs.into_iter();
+    let mut input = || inputs.next().unwrap();
+
+    match op {
+        Op::ScanOp(op) => PhysicalOp::ScanOp(Box::new(PhysScan {
+            file: op.file.clone(),
+            filetype: op.filetype.clone(),
+            tab_name: op.tab_name.clone(),
+            schema: op.schema.clone(),
+            sorted_by: op.sorted_by.clone(),
+            ls: op.ls.clone().unwrap(),
+            estimate,
+            cfg_name: Option::None,
+        })),
+        Op::FilterOp(op) => PhysicalOp::FilterOp(Box::new(PhysFilter {
+            input: input(),
+            field: op.field.clone(),
+            predicate: op.predicate.clone(),
+            ls: op.ls.clone().unwrap(),
+            estimate,
+            cfg_name: Option::None,
+        })),
+        Op::ProjectionOp(op) => PhysicalOp::ProjectionOp(Box::new(PhysProjection {
+            input: input(),
+            projection: op.projection.clone(),
+            ls: op.ls.clone().unwrap(),
+            estimate,
+            cfg_name: Option::None,
+        })),
+        Op::AggGroupOp(op) => PhysicalOp::AggregateOp(Box::new(PhysAggregate {
+            input: input(),
+            grouping: op.grouping.clone(),
+            agg_field: op.agg_field.clone(),
+            combines_partials: op.combines_partials,
+            ls: op.ls.clone().unwrap(),
+            estimate,
+            cfg_name: Option::None,
+        })),
+        Op::SortOp(op) => PhysicalOp::SortOp(Box::new(PhysSort {
+            input: input(),
+            order_columns: op.order_columns.clone(),
+            ls: op.ls.clone().unwrap(),
+            estimate,
+            cfg_name: Option::None,
+        })),
+        Op::LimitOp(op) => PhysicalOp::LimitOp(Box::new(PhysLimit {
+            input: input(),
+            limit: op.limit,
+            offset: op.offset,
+            ls: op.ls.clone().unwrap(),
+            estimate,
+            cfg_name: Option::None,
+        })),
+        Op::ComputeOp(op) => PhysicalOp::ComputeOp(Box::new(PhysCompute {
+            input: input(),
+            exprs: op.exprs.clone(),
+            ls: op.ls.clone().unwrap(),
+            estimate,
+            cfg_name: Option::None,
+        })),
+        Op::EmptyOp(op) => {
+            // The pruned input is never executed, it only provides the column types
+            let ls = op.ls.clone().unwrap();
+            PhysicalOp::EmptyOp(Box::new(PhysEmpty {
+                schema: ls.columns.iter().map(|c| op.input.column_type(c)).collect(),
+                ls,
+                estimate,
+                cfg_name: Option::None,
+            }))
+        }
+        Op::SubqueryProjOp(op) => PhysicalOp::RenameOp(Box::new(PhysRename {
+            input: input(),
+            ls: op.ls.clone().unwrap(),
+        })),
+        Op::JoinOp(_) | Op::CrossJoinOp(_) => unreachable!(),
+    }
+}
+
+impl Memo<'_, '_> {
+    fn insert(&mut self, op: Op, region: Option<usize>) -> GroupId {
+        let region = match (&op, region) {
+            (Op::JoinOp(_), Some(region)) => Option::Some(region),
+            (Op::JoinOp(_), None) => {
+                let mut columns = op.local_schema().unwrap().columns;
+                _join_tree_columns(&op, &mut columns);
+                self.regions.push(columns);
+                Option::Some(self.regions.len() - 1)
+            }
+            _ => Option::None,
+        };
+
+        let inputs: Vec<GroupId> = op
+            .inputs()
+            .into_iter()
+            .map(|input| self.insert(input.clone(), region))
+            .collect();
+
+        let id = self.groups.len();
+        let leaves = match op {
+            Op::JoinOp(_) => {
+                let mut leaves: Vec<GroupId> = inputs
+                    .iter()
+                    .flat_map(|i| self.groups[*i].leaves.clone())
+                    .collect();
+                leaves.sort_unstable();
+                self.joins.insert(leaves.clone(), id);
+                leaves
+            }
+            _ => vec![id],
+        };
+
+        self.groups.push(Group {
+            rows: stats::estimate_rows(&op, self.meta),
+            repr: op.clone(),
+            exprs: vec![MemoExpr { op, inputs }],
+            leaves,
+            region,
+        });
+        id
+    }
+
+    /**
+     * Applies the transformation rules until they produce no new expressions.
+     */
+    fn explore(&mut self) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in 0..self.groups.len() {
+                let mut idx = 0;
+                while idx < self.groups[id].exprs.len() {
+                    for (rule, expr) in self._transform(id, idx) {
+                        let after = if self.trace.enabled() {
+                            Option::Some(self._describe(&expr))
+                        } else {
+                            Option::None
+                        };
+                        if self._add_expr(id, expr) {
+                            changed = true;
+                            if let Some(after) = after {
+                                let before = self._describe(&self.groups[id].exprs[idx]);
+                                self.trace.record(TraceStep {
+                                    phase: "join_enumeration",
+                                    rule: rule.to_string(),
+                                    before,
+                                    after,
+                                    cost_before: Option::None,
+                                    cost_after: Option::None,
+                                });
+                            }
+                        }
+                    }
+                    idx += 1;
+                }
+            }
+        }
+    }
+
+    fn _add_expr(&mut self, id: GroupId, expr: MemoExpr) -> bool {
+        let same_join = |a: &MemoExpr| match (&a.op, &expr.op) {
+            (Op::JoinOp(a_join), Op::JoinOp(b_join)) => {
+                a.inputs == expr.inputs
+                    && a_join.build_join_attributes == b_join.build_join_attributes
+                    && a_join.probe_join_attributes == b_join.probe_join_attributes
+            }
+            _ => false,
+        };
+
+        if self.groups[id].exprs.iter().any(same_join) {
+            return false;
+        }
+        self.groups[id].exprs.push(expr);
+        true
+    }
+
+    fn _join_expr(
+        &self,
+        build: GroupId,
+        build_keys: &[ColRef],
+        probe: GroupId,
+        probe_keys: &[ColRef],
+        ls: LocalSchema,
+    ) -> MemoExpr {
+        let build_op = &self.groups[build].repr;
+        let probe_op = &self.groups[probe].repr;
+        MemoExpr {
+            op: Op::JoinOp(Box::new(OpJoin {
+                build: build_op.clone(),
+                build_join_attributes: build_keys.to_vec(),
+                probe: probe_op.clone(),
+                probe_join_attributes: probe_keys.to_vec(),
+                ls: Option::Some(ls),
+                vs: VirtualSchema::cat(&build_op.virtual_schema(), &probe_op.virtual_schema()),
+            })),
+            inputs: vec![build, probe],
+        }
+    }
+
+    /**
+     * The group joining two groups of the same join tree, created if no plan has it yet.
+     */
+    fn _join_group(
+        &mut self,
+        build: GroupId,
+        build_keys: &[ColRef],
+        probe: GroupId,
+        probe_keys: &[ColRef],
+        region: usize,
+    ) -> GroupId {
+        let mut leaves = self.groups[build].leaves.clone();
+        leaves.extend(self.groups[probe].leaves.iter().cloned());
+        leaves.sort_unstable();
+        if let Some(id) = self.joins.get(&leaves) {
+            return *id;
+        }
+
+        let build_vs = self.groups[build].repr.virtual_schema();
+        let probe_vs = self.groups[probe].repr.virtual_schema();
+        let columns = self.regions[region]
+            .iter()
+            .filter(|c| build_vs.contains(c) || probe_vs.contains(c))
+            .cloned()
+            .collect();
+        let expr = self._join_expr(
+            build,
+            build_keys,
+            probe,
+            probe_keys,
+            LocalSchema { columns },
+        );
+
+        let id = self.groups.len();
+        self.groups.push(Group {
+            rows: stats::estimate_rows(&expr.op, self.meta),
+            repr: expr.op.clone(),
+            exprs: vec![expr],
+            leaves: leaves.clone(),
+            region: Option::Some(region),
+        });
+        self.joins.insert(leaves, id);
+        id
+    }
+
+    /**
+     * Join commutativity, and associativity where the keys of the outer join all come from one
+     * input of the inner join, so no cross product is introduced.
+     */
+    fn _transform(&mut self, id: GroupId, idx: usize) -> Vec<(&'static str, MemoExpr)> {
+        let expr = self.groups[id].exprs[idx].clone();
+        let op_join = match &expr.op {
+            Op::JoinOp(op_join) => op_join,
+            _ => return vec![],
+        };
+        let (build, probe) = (expr.inputs[0], expr.inputs[1]);
+        let region = self.groups[id].region.unwrap();
+        let ls = op_join.ls.clone().unwrap();
+
+        let mut out = vec![(
+            "commute_join",
+            self._join_expr(
+                probe,
+                &op_join.probe_join_attributes,
+                build,
+                &op_join.build_join_attributes,
+                ls.clone(),
+            ),
+        )];
+
+        for inner in self.groups[build].exprs.clone() {
+            let inner_join = match &inner.op {
+                Op::JoinOp(inner_join) => inner_join,
+                _ => continue,
+            };
+            let (a, b) = (inner.inputs[0], inner.inputs[1]);
+            let from = |group: &Group| {
+                let vs = group.repr.virtual_schema();
+                op_join.build_join_attributes.iter().all(|c| vs.contains(c))
+            };
+
+            // (A JOIN B) JOIN C becomes A JOIN (B JOIN C) or (A JOIN C) JOIN B
+            if from(&self.groups[b]) {
+                let bc = self._join_group(
+                    b,
+                    &op_join.build_join_attributes,
+                    probe,
+                    &op_join.probe_join_attributes,
+                    region,
+                );
+                out.push((
+                    "associate_join",
+                    self._join_expr(
+                        a,
+                        &inner_join.build_join_attributes,
+                        bc,
+                        &inner_join.probe_join_attributes,
+                        ls.clone(),
+                    ),
+                ));
+            } else if from(&self.groups[a]) {
+                let ac = self._join_group(
+                    a,
+                    &op_join.build_join_attributes,
+                    probe,
+                    &op_join.probe_join_attributes,
+                    region,
+                );
+                out.push((
+                    "associate_join",
+                    self._join_expr(
+                        ac,
+                        &inner_join.build_join_attributes,
+                        b,
+                        &inner_join.probe_join_attributes,
+                        ls.clone(),
+                    ),
+                ));
+            }
+        }
+
+        out
+    }
+
+    /**
+     * Cheapest plan for a group whose output is sorted on `order`, with a sort on top if no
+     * implementation delivers that order by itself.
+     */
+    fn best(&mut self, id: GroupId, order: &[ColRef]) -> PhysicalOp {
+        let key = (id, order.to_vec());
+        if let Some(winner) = self.winners.get(&key) {
+            return winner.clone();
+        }
+
+        let mut candidates = Vec::new();
+        let mut planned = 0;
+        for expr in self.groups[id].exprs.clone() {
+            candidates.extend(self._implementations(id, &expr));
+            if planned == 0 {
+                planned = candidates.len();
+            }
+        }
+
+        let candidates: Vec<PhysicalOp> = candidates
+            .into_iter()
+            .map(|plan| {
+                if plan.output_order().starts_with(order) {
+                    plan
+                } else {
+                    _sort_on(plan, order, self.model)
+                }
+            })
+            .collect();
+        let cheapest = |plans: &[PhysicalOp]| {
+            plans
+                .iter()
+                .min_by(|a, b| a.estimate().total.partial_cmp(&b.estimate().total).unwrap())
+                .unwrap()
+                .clone()
+        };
+        let winner = cheapest(&candidates);
+
+        // Compare against the join order the planner wrote down
+        if self.groups[id].exprs.len() > 1 && self.trace.enabled() {
+            let initial = cheapest(&candidates[..planned]);
+            self.trace.record(TraceStep {
+                phase: "join_enumeration",
+                rule: "choose_cheapest".to_string(),
+                before: format!("{}", initial),
+                after: format!("{}", winner),
+                cost_before: Option::Some(initial.estimate().total),
+                cost_after: Option::Some(winner.estimate().total),
+            });
+        }
+
+        self.winners.insert(key, winner.clone());
+        winner
+    }
+
+    /**
+     * One line for an expression, naming its input groups by the tables they cover.
+     */
+    fn _describe(&self, expr: &MemoExpr) -> String {
+        let header = format!("{}", expr.op);
+        let inputs: Vec<String> = expr
+            .inputs
+            .iter()
+            .map(|i| {
+                let mut tables: Vec<String> = Vec::new();
+                for column in self.groups[*i].repr.virtual_schema().columns {
+                    if let ColRef::TableRef { table, .. } = column {
+                        if !tables.contains(&table) {
+                            tables.push(table);
+                        }
+                    }
+                }
+                format!("{{{}}}", tables.join(", "))
+            })
+            .collect();
+        format!("{} {}", header.lines().next().unwrap(), inputs.join(" x "))
+    }
+
+    /**
+     * Every way to execute an expression.
+     */
+    fn _implementations(&mut self, id: GroupId, expr: &MemoExpr) -> Vec<PhysicalOp> {
+        let rows = self.groups[id].rows;
+        let input_rows: f64 = expr.inputs.iter().map(|i| self.groups[*i].rows).sum();
+
+        match &expr.op {
+            Op::JoinOp(op_join) => self._implement_join(id, op_join, expr),
+            Op::CrossJoinOp(op_cross) => {
+                let build = self.best(expr.inputs[0], &[]);
+                let probe = self.best(expr.inputs[1], &[]);
+                let cost = self.model.nested_loop_join(
+                    self.groups[expr.inputs[0]].rows,
+                    self.groups[expr.inputs[1]].rows,
+                );
+                vec![PhysicalOp::JoinOp(Box::new(PhysJoin {
+                    algorithm: JoinAlgorithm::NestedLoop,
+                    estimate: _estimate(rows, cost, &[&build, &probe]),
+                    build,
+                    build_join_attributes: vec![],
+                    probe,
+                    probe_join_attributes: vec![],
+                    ls: op_cross.ls.clone().unwrap(),
+                    cfg_name: Option::None,
+                }))]
+            }
+            Op::LimitOp(op_limit) => {
+                let input = self.best(expr.inputs[0], &[]);
+                let estimate = _estimate(rows, self.model.pass_through(input_rows), &[&input]);
+                let mut out = vec![_implement(&expr.op, vec![input], estimate)];
+                if let Some(top_n) = self._implement_top_n(id, op_limit, expr.inputs[0]) {
+                    out.push(top_n);
+                }
+                out
+            }
+            _ => {
+                let cost = match &expr.op {
+                    Op::ScanOp(_) => self.model.scan(rows, self.groups[id].repr.row_width()),
+                    Op::SortOp(_) => self.model.sort(rows),
+                    Op::AggGroupOp(_) => self.model.aggregate(input_rows),
+                    Op::SubqueryProjOp(_) | Op::EmptyOp(_) => 0.0,
+                    _ => self.model.pass_through(input_rows),
+                };
+                let inputs: Vec<PhysicalOp> =
+                    expr.inputs.iter().map(|i| self.best(*i, &[])).collect();
+                let estimate = _estimate(rows, cost, &inputs.iter().collect::<Vec<_>>());
+                vec![_implement(&expr.op, inputs, estimate)]
+            }
+        }
+    }
+
+    /**
+     * A hash join if the build side fits in the memory budget, and sort-merge joins with the
+     * keys in the given order or in the order an input already arrives in.
+     */
+    fn _implement_join(
+        &mut self,
+        id: GroupId,
+        op_join: &OpJoin,
+        expr: &MemoExpr,
+    ) -> Vec<PhysicalOp> {
+        let (build, probe) = (expr.inputs[0], expr.inputs[1]);
+        let rows = self.groups[id].rows;
+        let build_rows = self.groups[build].rows;
+        let probe_rows = self.groups[probe].rows;
+        let build_keys = &op_join.build_join_attributes;
+        let probe_keys = &op_join.probe_join_attributes;
+        let join = |algorithm, cost, build, build_keys: &[ColRef], probe, probe_keys: &[ColRef]| {
+            PhysicalOp::JoinOp(Box::new(PhysJoin {
+                algorithm,
+                estimate: _estimate(rows, cost, &[&build, &probe]),
+                build,
+                build_join_attributes: build_keys.to_vec(),
+                probe,
+                probe_join_attributes: probe_keys.to_vec(),
+                ls: op_join.ls.clone().unwrap(),
+                cfg_name: Option::None,
+            }))
+        };
+
+        let mut out = Vec::new();
+
+        let build_plan = self.best(build, &[]);
+        let probe_plan = self.best(probe, &[]);
+        let build_bytes = build_rows * self.groups[build].repr.row_width() as f64;
+        if build_bytes <= self.model.memory_budget(self.meta) as f64 {
+            out.push(join(
+                JoinAlgorithm::Hash,
+                self.model.hash_join(build_rows, probe_rows),
+                build_plan.clone(),
+                build_keys,
+                probe_plan.clone(),
+                probe_keys,
+            ));
+        }
+
+        let mut key_orders = vec![(0..build_keys.len()).collect::<Vec<usize>>()];
+        for (plan, keys) in [(&build_plan, build_keys), (&probe_plan, probe_keys)] {
+            if let Some(positions) = _sorted_key_positions(&plan.output_order(), keys) {
+                if !key_orders.contains(&positions) {
+                    key_orders.push(positions);
+                }
+            }
+        }
+
+        for positions in key_orders {
+            let build_keys: Vec<ColRef> =
+                positions.iter().map(|&i| build_keys[i].clone()).collect();
+            let probe_keys: Vec<ColRef> =
+                positions.iter().map(|&i| probe_keys[i].clone()).collect();
+            let build_plan = self.best(build, &build_keys);
+            let probe_plan = self.best(probe, &probe_keys);
+            out.push(join(
+                JoinAlgorithm::SortMerge,
+                self.model.merge_join(build_rows, probe_rows),
+                build_plan,
+                &build_keys,
+                probe_plan,
+                &probe_keys,
+            ));
+        }
+
+        out
+    }
+
+    /**
+     * A limit directly above a sort can be a top-N, but only if the top-N heap fits in the
+     * memory budget.
+     */
+    fn _implement_top_n(
+        &mut self,
+        id: GroupId,
+        op_limit: &OpLimit,
+        input: GroupId,
+    ) -> Option<PhysicalOp> {
+        let limit = op_limit.limit?;
+        let sort = self.groups[input].exprs[0].clone();
+        let op_sort = match &sort.op {
+            Op::SortOp(op_sort) => op_sort,
+            _ => return Option::None,
+        };
+
+        let kept = limit + op_limit.offset;
+        let budget = self.model.memory_budget(self.meta);
+        if kept.saturating_mul(self.groups[input].repr.row_width()) > budget {
+            return Option::None;
+        }
+
+        let plan = self.best(sort.inputs[0], &[]);
+        let sorted_rows = self.groups[sort.inputs[0]].rows;
+        let top_n_rows = sorted_rows.min(kept as f64);
+        let top_n = PhysicalOp::TopNOp(Box::new(PhysTopN {
+            estimate: _estimate(top_n_rows, self.model.top_n(sorted_rows, kept), &[&plan]),
+            input: plan,
+            order_columns: op_sort.order_columns.clone(),
+            limit: kept,
+            ls: op_sort.ls.clone().unwrap(),
+            cfg_name: Option::None,
+        }));
+
+        if op_limit.offset == 0 {
+            return Option::Some(top_n);
+        }
+
+        // A top-N keeps the skipped tuples too, so a limit still has to drop them
+        Option::Some(PhysicalOp::LimitOp(Box::new(PhysLimit {
+            estimate: _estimate(
+                self.groups[id].rows,
+                self.model.pass_through(top_n_rows),
+                &[&top_n],
+            ),
+            input: top_n,
+            limit: op_limit.limit,
+            offset: op_limit.offset,
+            ls: op_limit.ls.clone().unwrap(),
+            cfg_name: Option::None,
+        })))
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -7,15 +9,105 @@ pub struct Metadata {
     pub tables: Vec<MetaTableDef>,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum MetaType {
     LONG,
     DEC,
+    INT,
+    DATE,
+    CHAR(u32),
+    VARCHAR(u32),
+}
+
+impl MetaType {
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, MetaType::LONG | MetaType::DEC | MetaType::INT)
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(self, MetaType::CHAR(_) | MetaType::VARCHAR(_))
+    }
+
+    /**
+     * The type both values can be represented in, if any. Numbers widen towards `DEC` and text
+     * towards the longer `VARCHAR`.
+     */
+    pub fn common_with(&self, other: &MetaType) -> Option<MetaType> {
+        if self == other {
+            return Option::Some(*self);
+        }
+
+        if self.is_numeric() && other.is_numeric() {
+            return if *self == MetaType::DEC || *other == MetaType::DEC {
+                Option::Some(MetaType::DEC)
+            } else if *self == MetaType::LONG || *other == MetaType::LONG {
+                Option::Some(MetaType::LONG)
+            } else {
+                Option::Some(MetaType::INT)
+            };
+        }
+
+        if self.is_text() && other.is_text() {
+            return Option::Some(MetaType::VARCHAR(self.width().max(other.width()) as u32));
+        }
+
+        Option::None
+    }
+
+    /**
+     * Whether `CAST` may convert a value of this type to `target`. Text is parsed at runtime.
+     */
+    pub fn can_cast_to(&self, target: &MetaType) -> bool {
+        self.common_with(target).is_some() || self.is_text() || target.is_text()
+    }
+
+    /**
+     * Bytes a value of this type takes up in a tuple.
+     */
+    pub fn width(&self) -> u64 {
+        match self {
+            MetaType::LONG | MetaType::DEC | MetaType::DATE => 8,
+            MetaType::INT => 4,
+            MetaType::CHAR(n) | MetaType::VARCHAR(n) => *n as u64,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MetaSchema {
     pub columns: Vec<(String, MetaType)>,
+    /**
+     * Columns that may contain NULL, all others are NOT NULL.
+     */
+    #[serde(default)]
+    pub nullable: Vec<String>,
+}
+
+/**
+ * Optional per-column statistics, bounds are written the same way as SQL constants.
+ */
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MetaColumnStats {
+    pub min: Option<String>,
+    pub max: Option<String>,
+    pub distinct: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MetaTableStats {
+    pub rows: u64,
+    #[serde(default)]
+    pub columns: HashMap<String, MetaColumnStats>,
+}
+
+/**
+ * Columns whose values all appear in the primary key of `references`, matched in order.
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetaForeignKey {
+    pub columns: Vec<String>,
+    pub references: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,6 +116,19 @@ pub struct MetaTableDef {
     pub file: String,
     pub filetype: String,
     pub schema: MetaSchema,
+    pub stats: Option<MetaTableStats>,
+    /**
+     * Columns the file is sorted on in ascending order, most significant first.
+     */
+    #[serde(default)]
+    pub sorted_by: Vec<String>,
+    /**
+     * Columns no two tuples agree on all of.
+     */
+    #[serde(default)]
+    pub primary_key: Vec<String>,
+    #[serde(default)]
+    pub foreign_keys: Vec<MetaForeignKey>,
 }
 
 impl Metadata {
@@ -42,7 +147,12 @@ impl Metadata {
                             ("PKEY".to_string(), MetaType::LONG),
                             ("PRICE".to_string(), MetaType::DEC),
                         ],
+                        nullable: vec![],
                     },
+                    stats: Option::None,
+                    sorted_by: vec![],
+                    primary_key: vec![],
+                    foreign_keys: vec![],
                 },
                 MetaTableDef {
                     name: "ORDERS".to_string(),
@@ -53,7 +163,12 @@ impl Metadata {
                             ("OKEY".to_string(), MetaType::LONG),
                             ("ZIP".to_string(), MetaType::LONG),
                         ],
+                        nullable: vec![],
                     },
+                    stats: Option::None,
+                    sorted_by: vec![],
+                    primary_key: vec![],
+                    foreign_keys: vec![],
                 },
                 MetaTableDef {
                     name: "PART".to_string(),
@@ -64,7 +179,12 @@ impl Metadata {
                             ("PKEY".to_string(), MetaType::LONG),
                             ("COST".to_string(), MetaType::DEC),
                         ],
+                        nullable: vec![],
                     },
+                    stats: Option::None,
+                    sorted_by: vec![],
+                    primary_key: vec![],
+                    foreign_keys: vec![],
                 },
             ],
         }
@@ -1,12 +1,32 @@
+use std::cmp::Ordering;
 use std::fmt;
 
 use crate::metadata::MetaType;
 use crate::planner::{LocalSchema, VirtualSchema};
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ColRef {
     TableRef { table: String, column: String },
     AggregateRef { func: AggFunc, source: Box<ColRef> },
+    ComputedRef { expr: Box<ScalarExpr> },
+}
+
+impl ColRef {
+    /**
+     * The aggregates this column depends on, looking through computed expressions.
+     */
+    pub fn aggregates(&self) -> Vec<ColRef> {
+        match self {
+            ColRef::TableRef { .. } => vec![],
+            ColRef::AggregateRef { .. } => vec![self.clone()],
+            ColRef::ComputedRef { expr } => expr
+                .source_columns()
+                .iter()
+                .flat_map(|c| c.aggregates())
+                .collect(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -23,17 +43,350 @@ impl AggFunc {
             _ => Option::None,
         }
     }
+
+    pub fn output_type(&self, input: MetaType) -> Result<MetaType, String> {
+        match self {
+            AggFunc::Count => Ok(MetaType::LONG),
+            AggFunc::Sum => match input {
+                MetaType::INT | MetaType::LONG => Ok(MetaType::LONG),
+                MetaType::DEC => Ok(MetaType::DEC),
+                _ => Err(format!("can't sum values of type {:?}", input)),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Literal {
+    Integer(String),
+    Decimal(String),
+    Text(String),
+    Date(String),
+}
+
+impl Literal {
+    pub fn from_number(value: &str) -> Literal {
+        if value.contains(['.', 'e', 'E']) {
+            Literal::Decimal(value.to_string())
+        } else {
+            Literal::Integer(value.to_string())
+        }
+    }
+
+    /**
+     * Dates must be written as `YYYY-MM-DD`.
+     */
+    pub fn from_date(value: &str) -> Literal {
+        Literal::parse_date(value)
+            .unwrap_or_else(|| panic!("Invalid date literal '{}', expected YYYY-MM-DD", value))
+    }
+
+    pub fn parse_date(value: &str) -> Option<Literal> {
+        let parts: Vec<&str> = value.split('-').collect();
+        let valid = parts.len() == 3
+            && [4, 2, 2]
+                .iter()
+                .zip(&parts)
+                .all(|(len, p)| p.len() == *len && p.chars().all(|c| c.is_ascii_digit()));
+
+        if valid {
+            Option::Some(Literal::Date(value.to_string()))
+        } else {
+            Option::None
+        }
+    }
+
+    pub fn meta_type(&self) -> MetaType {
+        match self {
+            Literal::Integer(_) => MetaType::LONG,
+            Literal::Decimal(_) => MetaType::DEC,
+            Literal::Text(value) => MetaType::CHAR(value.len() as u32),
+            Literal::Date(_) => MetaType::DATE,
+        }
+    }
+
+    /**
+     * Orders two constants by value, if they are of comparable kinds. Text compared against a
+     * date is read as a date.
+     */
+    pub fn compare(&self, other: &Literal) -> Option<Ordering> {
+        match (self, other) {
+            (Literal::Integer(a), Literal::Integer(b)) => {
+                Option::Some(a.parse::<i128>().ok()?.cmp(&b.parse::<i128>().ok()?))
+            }
+            (
+                Literal::Integer(a) | Literal::Decimal(a),
+                Literal::Integer(b) | Literal::Decimal(b),
+            ) => a.parse::<f64>().ok()?.partial_cmp(&b.parse::<f64>().ok()?),
+            (Literal::Text(a), Literal::Text(b)) => Option::Some(a.cmp(b)),
+            // Dates are fixed width, so they sort like their text
+            (Literal::Date(a), Literal::Date(b)) => Option::Some(a.cmp(b)),
+            (Literal::Date(_), Literal::Text(text)) => self.compare(&Literal::parse_date(text)?),
+            (Literal::Text(text), Literal::Date(_)) => Literal::parse_date(text)?.compare(other),
+            _ => Option::None,
+        }
+    }
+
+    /**
+     * The bare value, as the engine parses it against the column type.
+     */
+    pub fn raw(&self) -> &str {
+        match self {
+            Literal::Integer(value)
+            | Literal::Decimal(value)
+            | Literal::Text(value)
+            | Literal::Date(value) => value,
+        }
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+impl ArithOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            ArithOp::Add => "+",
+            ArithOp::Sub => "-",
+            ArithOp::Mul => "*",
+            ArithOp::Div => "/",
+            ArithOp::Mod => "%",
+        }
+    }
+}
+
+/**
+ * Scalar expression tree evaluated per tuple by a compute op.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ScalarExpr {
+    Column(ColRef),
+    Literal(Literal),
+    Arith {
+        op: ArithOp,
+        left: Box<ScalarExpr>,
+        right: Box<ScalarExpr>,
+    },
+    /**
+     * The result of the first branch whose condition holds, else `else_result`, else NULL.
+     */
+    Case {
+        branches: Vec<(ScalarCondition, ScalarExpr)>,
+        else_result: Option<Box<ScalarExpr>>,
+    },
+    Cast {
+        expr: Box<ScalarExpr>,
+        to: MetaType,
+    },
+    /**
+     * A `NULL` result of a `CASE` branch, typed like the other results.
+     */
+    Null,
+}
+
+/**
+ * Per-tuple test used by `CASE WHEN`. Comparison ops use the same spelling as filters.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ScalarCondition {
+    Compare {
+        op: String,
+        left: ScalarExpr,
+        right: ScalarExpr,
+    },
+    IsNull {
+        expr: ScalarExpr,
+        negated: bool,
+    },
+    And(Vec<ScalarCondition>),
+    Or(Vec<ScalarCondition>),
+}
+
+impl ScalarCondition {
+    /**
+     * Checks that every comparison in this condition is between types that mix.
+     */
+    pub fn check_types(&self, input: &Op) -> Result<(), String> {
+        match self {
+            ScalarCondition::Compare { op: _, left, right } => {
+                let left_type = left.result_type(input)?;
+                let right_type = right.result_type(input)?;
+                if left_type.common_with(&right_type).is_none() {
+                    return Err(format!(
+                        "{}: can't compare {:?} with {:?}",
+                        self, left_type, right_type
+                    ));
+                }
+                Ok(())
+            }
+            ScalarCondition::IsNull { expr, negated: _ } => expr.result_type(input).map(|_| ()),
+            ScalarCondition::And(conditions) | ScalarCondition::Or(conditions) => conditions
+                .iter()
+                .try_for_each(|condition| condition.check_types(input)),
+        }
+    }
+
+    fn _collect_source_columns(&self, out: &mut Vec<ColRef>) {
+        match self {
+            ScalarCondition::Compare { op: _, left, right } => {
+                left._collect_source_columns(out);
+                right._collect_source_columns(out);
+            }
+            ScalarCondition::IsNull { expr, negated: _ } => expr._collect_source_columns(out),
+            ScalarCondition::And(conditions) | ScalarCondition::Or(conditions) => {
+                for condition in conditions {
+                    condition._collect_source_columns(out);
+                }
+            }
+        }
+    }
+}
+
+impl ScalarExpr {
+    /**
+     * Columns that must be present in the input to evaluate this expression.
+     */
+    pub fn source_columns(&self) -> Vec<ColRef> {
+        let mut out = Vec::new();
+        self._collect_source_columns(&mut out);
+        out
+    }
+
+    /**
+     * Type this expression evaluates to over `input`, or why it can't be evaluated.
+     */
+    pub fn result_type(&self, input: &Op) -> Result<MetaType, String> {
+        match self {
+            ScalarExpr::Column(colref) => input.try_column_type(colref),
+            ScalarExpr::Literal(literal) => Ok(literal.meta_type()),
+            ScalarExpr::Arith { op, left, right } => {
+                let left_type = left.result_type(input)?;
+                let right_type = right.result_type(input)?;
+                if !left_type.is_numeric() || !right_type.is_numeric() {
+                    return Err(format!(
+                        "{}: can't apply {} to {:?} and {:?}",
+                        self,
+                        op.symbol(),
+                        left_type,
+                        right_type
+                    ));
+                }
+
+                Ok(left_type.common_with(&right_type).unwrap())
+            }
+            ScalarExpr::Case {
+                branches,
+                else_result,
+            } => {
+                for (condition, _) in branches {
+                    condition.check_types(input)?;
+                }
+
+                let mut result_type: Option<MetaType> = Option::None;
+                let results = branches
+                    .iter()
+                    .map(|(_, result)| result)
+                    .chain(else_result.iter().map(|e| e.as_ref()))
+                    .filter(|result| result != &&ScalarExpr::Null);
+                for result in results {
+                    let branch_type = result.result_type(input)?;
+                    result_type = match result_type {
+                        None => Option::Some(branch_type),
+                        Some(t) => Option::Some(t.common_with(&branch_type).ok_or_else(|| {
+                            format!(
+                                "{}: results of types {:?} and {:?} don't mix",
+                                self, t, branch_type
+                            )
+                        })?),
+                    };
+                }
+                result_type.ok_or_else(|| format!("{}: needs a result that isn't NULL", self))
+            }
+            ScalarExpr::Cast { expr, to } => {
+                let from = expr.result_type(input)?;
+                if !from.can_cast_to(to) {
+                    return Err(format!("{}: can't cast {:?} to {:?}", self, from, to));
+                }
+                Ok(*to)
+            }
+            ScalarExpr::Null => Err("NULL: only supported as a CASE result".to_string()),
+        }
+    }
+
+    /**
+     * Whether evaluating this expression over `input` may yield NULL.
+     */
+    pub fn nullable(&self, input: &Op) -> bool {
+        match self {
+            ScalarExpr::Column(colref) => input.column_nullable(colref),
+            ScalarExpr::Literal(_) => false,
+            ScalarExpr::Arith { op: _, left, right } => {
+                left.nullable(input) || right.nullable(input)
+            }
+            ScalarExpr::Case {
+                branches,
+                else_result,
+            } => match else_result {
+                Some(else_result) => {
+                    else_result.nullable(input) || branches.iter().any(|(_, r)| r.nullable(input))
+                }
+                None => true,
+            },
+            ScalarExpr::Cast { expr, to: _ } => expr.nullable(input),
+            ScalarExpr::Null => true,
+        }
+    }
+
+    fn _collect_source_columns(&self, out: &mut Vec<ColRef>) {
+        match self {
+            ScalarExpr::Column(colref) => {
+                if !out.contains(colref) {
+                    out.push(colref.clone());
+                }
+            }
+            ScalarExpr::Literal(_) => {}
+            ScalarExpr::Arith { op: _, left, right } => {
+                left._collect_source_columns(out);
+                right._collect_source_columns(out);
+            }
+            ScalarExpr::Case {
+                branches,
+                else_result,
+            } => {
+                for (condition, result) in branches {
+                    condition._collect_source_columns(out);
+                    result._collect_source_columns(out);
+                }
+                if let Some(else_result) = else_result {
+                    else_result._collect_source_columns(out);
+                }
+            }
+            ScalarExpr::Cast { expr, to: _ } => expr._collect_source_columns(out),
+            ScalarExpr::Null => {}
+        }
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone)]
 pub enum Op {
     ScanOp(Box<OpScan>),
     JoinOp(Box<OpJoin>),
+    CrossJoinOp(Box<OpCrossJoin>),
     FilterOp(Box<OpFilter>),
     ProjectionOp(Box<OpProjection>),
     AggGroupOp(Box<OpAggGroup>),
-    SortLimitOp(Box<OpSortLimit>),
+    SortOp(Box<OpSort>),
+    LimitOp(Box<OpLimit>),
     SubqueryProjOp(Box<OpSubqueryProj>),
+    ComputeOp(Box<OpCompute>),
+    EmptyOp(Box<OpEmpty>),
 }
 
 impl Op {
@@ -42,10 +395,228 @@ impl Op {
             Op::ScanOp(op) => op.vs.clone(),
             Op::FilterOp(op) => op.vs.clone(),
             Op::JoinOp(op) => op.vs.clone(),
+            Op::CrossJoinOp(op) => op.vs.clone(),
             Op::ProjectionOp(op) => op.vs.clone(),
             Op::AggGroupOp(op) => op.vs.clone(),
-            Op::SortLimitOp(op) => op.vs.clone(),
+            Op::SortOp(op) => op.vs.clone(),
+            Op::LimitOp(op) => op.vs.clone(),
             Op::SubqueryProjOp(op) => op.vs.clone(),
+            Op::ComputeOp(op) => op.vs.clone(),
+            Op::EmptyOp(op) => op.vs.clone(),
+        }
+    }
+
+    /**
+     * The ops whose output this op consumes. An empty op consumes nothing.
+     */
+    pub fn inputs(&self) -> Vec<&Op> {
+        match self {
+            Op::ScanOp(_) | Op::EmptyOp(_) => vec![],
+            Op::JoinOp(op) => vec![&op.build, &op.probe],
+            Op::CrossJoinOp(op) => vec![&op.build, &op.probe],
+            Op::FilterOp(op) => vec![&op.input],
+            Op::ProjectionOp(op) => vec![&op.input],
+            Op::AggGroupOp(op) => vec![&op.input],
+            Op::SortOp(op) => vec![&op.input],
+            Op::LimitOp(op) => vec![&op.input],
+            Op::SubqueryProjOp(op) => vec![&op.input],
+            Op::ComputeOp(op) => vec![&op.input],
+        }
+    }
+
+    /**
+     * Bytes per output tuple. Expects local schemas to be assigned already.
+     */
+    pub fn row_width(&self) -> u64 {
+        self.local_schema()
+            .unwrap()
+            .columns
+            .iter()
+            .map(|c| self.column_type(c).width())
+            .sum()
+    }
+
+    pub fn contains_cross_join(&self) -> bool {
+        matches!(self, Op::CrossJoinOp(_)) || self.inputs().iter().any(|i| i.contains_cross_join())
+    }
+
+    /**
+     * Rebuilds this op with every input replaced by `f(input)`.
+     */
+    pub fn map_inputs<F: FnMut(Op) -> Op>(self, mut f: F) -> Op {
+        match self {
+            Op::ScanOp(op) => Op::ScanOp(op),
+            Op::JoinOp(mut op) => {
+                op.build = f(op.build);
+                op.probe = f(op.probe);
+                Op::JoinOp(op)
+            }
+            Op::CrossJoinOp(mut op) => {
+                op.build = f(op.build);
+                op.probe = f(op.probe);
+                Op::CrossJoinOp(op)
+            }
+            Op::FilterOp(mut op) => {
+                op.input = f(op.input);
+                Op::FilterOp(op)
+            }
+            Op::ProjectionOp(mut op) => {
+                op.input = f(op.input);
+                Op::ProjectionOp(op)
+            }
+            Op::AggGroupOp(mut op) => {
+                op.input = f(op.input);
+                Op::AggGroupOp(op)
+            }
+            Op::SortOp(mut op) => {
+                op.input = f(op.input);
+                Op::SortOp(op)
+            }
+            Op::LimitOp(mut op) => {
+                op.input = f(op.input);
+                Op::LimitOp(op)
+            }
+            Op::SubqueryProjOp(mut op) => {
+                op.input = f(op.input);
+                Op::SubqueryProjOp(op)
+            }
+            Op::ComputeOp(mut op) => {
+                op.input = f(op.input);
+                Op::ComputeOp(op)
+            }
+            Op::EmptyOp(mut op) => {
+                op.input = f(op.input);
+                Op::EmptyOp(op)
+            }
+        }
+    }
+
+    /**
+     * Type of a column produced by this op. Only for plans that passed type checking.
+     */
+    pub fn column_type(&self, colref: &ColRef) -> MetaType {
+        self.try_column_type(colref)
+            .unwrap_or_else(|e| panic!("Unchecked type error: {}", e))
+    }
+
+    /**
+     * Type of a column produced by this op, found by walking down to where it is defined. Fails
+     * if the column is computed by an expression that doesn't type check.
+     */
+    pub fn try_column_type(&self, colref: &ColRef) -> Result<MetaType, String> {
+        match self {
+            Op::ScanOp(op) => {
+                let idx = op
+                    .vs
+                    .columns
+                    .iter()
+                    .position(|c| c == colref)
+                    .unwrap_or_else(|| panic!("Column {} is not produced by scan", colref));
+                Ok(op.schema[idx])
+            }
+            Op::JoinOp(op) => {
+                if op.build.virtual_schema().contains(colref) {
+                    op.build.try_column_type(colref)
+                } else {
+                    op.probe.try_column_type(colref)
+                }
+            }
+            Op::CrossJoinOp(op) => {
+                if op.build.virtual_schema().contains(colref) {
+                    op.build.try_column_type(colref)
+                } else {
+                    op.probe.try_column_type(colref)
+                }
+            }
+            Op::FilterOp(op) => op.input.try_column_type(colref),
+            Op::ProjectionOp(op) => op.input.try_column_type(colref),
+            Op::SortOp(op) => op.input.try_column_type(colref),
+            Op::LimitOp(op) => op.input.try_column_type(colref),
+            Op::AggGroupOp(op) => match colref {
+                ColRef::AggregateRef { func, source: _ } => {
+                    func.output_type(op.input.try_column_type(&op.source())?)
+                }
+                _ => op.input.try_column_type(colref),
+            },
+            Op::ComputeOp(op) => match colref {
+                ColRef::ComputedRef { expr } if op.exprs.contains(colref) => {
+                    expr.result_type(&op.input)
+                }
+                _ => op.input.try_column_type(colref),
+            },
+            Op::EmptyOp(op) => op.input.try_column_type(colref),
+            Op::SubqueryProjOp(op) => {
+                // Subquery columns are renamed positionally
+                let idx = op
+                    .vs
+                    .columns
+                    .iter()
+                    .position(|c| c == colref)
+                    .unwrap_or_else(|| panic!("Column {} is not produced by subquery", colref));
+                let inner = &op.input.local_schema().unwrap().columns[idx];
+                op.input.try_column_type(inner)
+            }
+        }
+    }
+
+    /**
+     * Whether a column produced by this op may be NULL.
+     */
+    pub fn column_nullable(&self, colref: &ColRef) -> bool {
+        match self {
+            Op::ScanOp(op) => {
+                let idx = op
+                    .vs
+                    .columns
+                    .iter()
+                    .position(|c| c == colref)
+                    .unwrap_or_else(|| panic!("Column {} is not produced by scan", colref));
+                op.nullable[idx]
+            }
+            Op::JoinOp(op) => {
+                if op.build.virtual_schema().contains(colref) {
+                    op.build.column_nullable(colref)
+                } else {
+                    op.probe.column_nullable(colref)
+                }
+            }
+            Op::CrossJoinOp(op) => {
+                if op.build.virtual_schema().contains(colref) {
+                    op.build.column_nullable(colref)
+                } else {
+                    op.probe.column_nullable(colref)
+                }
+            }
+            Op::AggGroupOp(op) => match colref {
+                ColRef::AggregateRef {
+                    func: AggFunc::Count,
+                    source: _,
+                } => false,
+                // A sum over only NULLs is NULL
+                ColRef::AggregateRef { .. } => op.input.column_nullable(&op.source()),
+                _ => op.input.column_nullable(colref),
+            },
+            Op::ComputeOp(op) => match colref {
+                ColRef::ComputedRef { expr } if op.exprs.contains(colref) => {
+                    expr.nullable(&op.input)
+                }
+                _ => op.input.column_nullable(colref),
+            },
+            Op::SubqueryProjOp(op) => {
+                let idx = op
+                    .vs
+                    .columns
+                    .iter()
+                    .position(|c| c == colref)
+                    .unwrap_or_else(|| panic!("Column {} is not produced by subquery", colref));
+                let inner = &op.input.local_schema().unwrap().columns[idx];
+                op.input.column_nullable(inner)
+            }
+            Op::FilterOp(op) => op.input.column_nullable(colref),
+            Op::ProjectionOp(op) => op.input.column_nullable(colref),
+            Op::SortOp(op) => op.input.column_nullable(colref),
+            Op::LimitOp(op) => op.input.column_nullable(colref),
+            Op::EmptyOp(op) => op.input.column_nullable(colref),
         }
     }
 
@@ -54,10 +625,14 @@ impl Op {
             Op::ScanOp(op) => op.ls.clone(),
             Op::FilterOp(op) => op.ls.clone(),
             Op::JoinOp(op) => op.ls.clone(),
+            Op::CrossJoinOp(op) => op.ls.clone(),
             Op::ProjectionOp(op) => op.ls.clone(),
             Op::AggGroupOp(op) => op.ls.clone(),
-            Op::SortLimitOp(op) => op.ls.clone(),
+            Op::SortOp(op) => op.ls.clone(),
+            Op::LimitOp(op) => op.ls.clone(),
             Op::SubqueryProjOp(op) => op.ls.clone(),
+            Op::ComputeOp(op) => op.ls.clone(),
+            Op::EmptyOp(op) => op.ls.clone(),
         }
     }
 }
@@ -68,31 +643,272 @@ pub struct OpScan {
     pub filetype: String,
     pub tab_name: String,
     pub schema: Vec<MetaType>,
+    pub nullable: Vec<bool>,
+    pub sorted_by: Vec<ColRef>,
+    pub primary_key: Vec<ColRef>,
+    pub foreign_keys: Vec<ForeignKey>,
     pub ls: Option<LocalSchema>,
     pub vs: VirtualSchema,
-    pub cfg_name: Option<String>,
+}
+
+/**
+ * Columns of a scan that reference the primary key of table `references`, matched in order.
+ */
+#[derive(Debug, Clone)]
+pub struct ForeignKey {
+    pub columns: Vec<ColRef>,
+    pub references: String,
 }
 
 #[derive(Debug, Clone)]
 pub struct OpJoin {
     pub build: Op,
-    pub build_join_attribute: ColRef,
+    /**
+     * Join keys, compared pairwise with `probe_join_attributes`.
+     */
+    pub build_join_attributes: Vec<ColRef>,
+    pub probe: Op,
+    pub probe_join_attributes: Vec<ColRef>,
+    pub ls: Option<LocalSchema>,
+    pub vs: VirtualSchema,
+}
+
+/**
+ * The scan that produces a column, unless it is hidden behind a subquery.
+ */
+fn _scan_producing<'a>(op: &'a Op, column: &ColRef) -> Option<&'a OpScan> {
+    match op {
+        Op::ScanOp(op_scan) if op_scan.vs.contains(column) => Option::Some(op_scan),
+        Op::SubqueryProjOp(_) => Option::None,
+        _ => op
+            .inputs()
+            .into_iter()
+            .find_map(|input| _scan_producing(input, column)),
+    }
+}
+
+/**
+ * Whether every tuple of `fact` matches exactly one tuple of `dimension`: the dimension is a
+ * whole table joined on its primary key, and the fact keys are a NOT NULL foreign key to it.
+ */
+fn _matches_once(
+    fact: &Op,
+    fact_keys: &[ColRef],
+    dimension: &Op,
+    dimension_keys: &[ColRef],
+) -> bool {
+    // Projections only narrow the scan, a filter would drop tuples the fact side references
+    let mut base = dimension;
+    while let Op::ProjectionOp(op_projection) = base {
+        base = &op_projection.input;
+    }
+    let dimension_scan = match base {
+        Op::ScanOp(op_scan) => op_scan,
+        _ => return false,
+    };
+
+    let primary_key = &dimension_scan.primary_key;
+    if primary_key.is_empty()
+        || dimension_keys.len() != primary_key.len()
+        || !primary_key.iter().all(|k| dimension_keys.contains(k))
+        || fact_keys.iter().any(|k| fact.column_nullable(k))
+    {
+        return false;
+    }
+
+    let fact_scan = match _scan_producing(fact, &fact_keys[0]) {
+        Some(op_scan) => op_scan,
+        None => return false,
+    };
+    fact_scan.foreign_keys.iter().any(|fk| {
+        fk.references == dimension_scan.tab_name
+            && fk.columns.len() == primary_key.len()
+            && dimension_keys.iter().zip(fact_keys).all(|(d, f)| {
+                let idx = primary_key.iter().position(|k| k == d).unwrap();
+                fk.columns[idx] == *f
+            })
+    })
+}
+
+impl OpJoin {
+    /**
+     * Whether every build tuple matches exactly one probe tuple.
+     */
+    pub fn build_matches_once(&self) -> bool {
+        _matches_once(
+            &self.build,
+            &self.build_join_attributes,
+            &self.probe,
+            &self.probe_join_attributes,
+        )
+    }
+
+    /**
+     * Whether every probe tuple matches exactly one build tuple.
+     */
+    pub fn probe_matches_once(&self) -> bool {
+        _matches_once(
+            &self.probe,
+            &self.probe_join_attributes,
+            &self.build,
+            &self.build_join_attributes,
+        )
+    }
+}
+
+/**
+ * Pairs every build tuple with every probe tuple.
+ */
+#[derive(Debug, Clone)]
+pub struct OpCrossJoin {
+    pub build: Op,
     pub probe: Op,
-    pub probe_join_attribute: ColRef,
     pub ls: Option<LocalSchema>,
     pub vs: VirtualSchema,
-    pub cfg_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MatchMode {
+    Prefix,
+    Contains,
+    Regex,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FilterPredicate {
+    Compare {
+        op: String,
+        value: Literal,
+    },
+    /**
+     * A lowered `LIKE`, `pattern` is the prefix, substring or anchored regex depending on `mode`.
+     */
+    Match {
+        mode: MatchMode,
+        pattern: String,
+        negated: bool,
+        case_insensitive: bool,
+    },
+    IsNull {
+        negated: bool,
+    },
+    /**
+     * Compares the field with another column of the same tuple, e.g. a join predicate that the
+     * join tree couldn't use.
+     */
+    CompareColumns {
+        op: String,
+        other: ColRef,
+    },
+}
+
+fn _regex_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if ".^$*+?()[]{}|\\".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+impl FilterPredicate {
+    /**
+     * Columns besides the filtered field that the predicate reads.
+     */
+    pub fn other_columns(&self) -> Vec<ColRef> {
+        match self {
+            FilterPredicate::CompareColumns { op: _, other } => vec![other.clone()],
+            _ => vec![],
+        }
+    }
+
+    /**
+     * Picks the cheapest filter that implements a `LIKE` pattern: plain equality, a prefix or
+     * substring match, and an anchored regex for anything else.
+     */
+    pub fn from_like(pattern: &str, negated: bool, case_insensitive: bool) -> FilterPredicate {
+        let is_plain = |s: &str| !s.contains(['%', '_']);
+        let matching = |mode, pattern: &str| FilterPredicate::Match {
+            mode,
+            pattern: pattern.to_string(),
+            negated,
+            case_insensitive,
+        };
+
+        if is_plain(pattern) && !case_insensitive {
+            return FilterPredicate::Compare {
+                op: if negated { "!=" } else { "==" }.to_string(),
+                value: Literal::Text(pattern.to_string()),
+            };
+        }
+
+        if let Some(prefix) = pattern.strip_suffix('%') {
+            if is_plain(prefix) {
+                return matching(MatchMode::Prefix, prefix);
+            }
+            if let Some(infix) = prefix.strip_prefix('%') {
+                if is_plain(infix) {
+                    return matching(MatchMode::Contains, infix);
+                }
+            }
+        }
+
+        let mut regex = "^".to_string();
+        for c in pattern.chars() {
+            match c {
+                '%' => regex.push_str(".*"),
+                '_' => regex.push('.'),
+                _ => regex.push_str(&_regex_escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+
+        matching(MatchMode::Regex, &regex)
+    }
+
+    /**
+     * Bounds of the values this predicate keeps as `(lower, upper)`, where the lower bound is
+     * inclusive for `>=` and prefixes. A prefix match `'abc%'` is the range `['abc', 'abd')`.
+     */
+    pub fn as_range(&self) -> Option<(Option<Literal>, Option<Literal>)> {
+        match self {
+            FilterPredicate::Compare { op, value } => match op.as_str() {
+                "<" | "<=" => Option::Some((Option::None, Option::Some(value.clone()))),
+                ">" | ">=" => Option::Some((Option::Some(value.clone()), Option::None)),
+                "==" => Option::Some((Option::Some(value.clone()), Option::Some(value.clone()))),
+                _ => Option::None,
+            },
+            FilterPredicate::Match {
+                mode: MatchMode::Prefix,
+                pattern,
+                negated: false,
+                case_insensitive: false,
+            } => {
+                let lower = Literal::Text(pattern.clone());
+                let mut upper = pattern.clone();
+                match upper.pop() {
+                    Some(last) => match std::char::from_u32(last as u32 + 1) {
+                        Some(next) => upper.push(next),
+                        None => return Option::Some((Option::Some(lower), Option::None)),
+                    },
+                    None => return Option::None,
+                }
+                Option::Some((Option::Some(lower), Option::Some(Literal::Text(upper))))
+            }
+            _ => Option::None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct OpFilter {
     pub input: Op,
-    pub op: String,
     pub field: ColRef,
-    pub value: String,
+    pub predicate: FilterPredicate,
     pub ls: Option<LocalSchema>,
     pub vs: VirtualSchema,
-    pub cfg_name: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -101,7 +917,6 @@ pub struct OpProjection {
     pub projection: Vec<ColRef>,
     pub ls: Option<LocalSchema>,
     pub vs: VirtualSchema,
-    pub cfg_name: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -109,26 +924,69 @@ pub struct OpAggGroup {
     pub input: Op,
     pub grouping: Vec<ColRef>,
     pub agg_field: ColRef,
+    /**
+     * The input already holds partial aggregates in `agg_field`, which are summed per group
+     * instead of aggregating the source column again.
+     */
+    pub combines_partials: bool,
     pub ls: Option<LocalSchema>,
     pub vs: VirtualSchema,
-    pub cfg_name: Option<String>,
 }
 
+impl OpAggGroup {
+    /**
+     * The input column that is aggregated.
+     */
+    pub fn source(&self) -> ColRef {
+        match &self.agg_field {
+            _ if self.combines_partials => self.agg_field.clone(),
+            ColRef::AggregateRef { func: _, source } => source.as_ref().clone(),
+            _ => panic!("Agg field must be aggregate type"),
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Order {
     ASC,
     DESC,
 }
 
+impl Order {
+    /**
+     * Matches postgres: NULLs sort as if larger than any value.
+     */
+    pub fn default_nulls_first(&self) -> bool {
+        self == &Order::DESC
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderColumn {
+    pub column: ColRef,
+    pub order: Order,
+    pub nulls_first: bool,
+}
+
 #[derive(Debug, Clone)]
-pub struct OpSortLimit {
+pub struct OpSort {
     pub input: Op,
-    pub order_columns: Vec<ColRef>,
-    pub order: Option<Order>,
-    pub limit: u64,
+    pub order_columns: Vec<OrderColumn>,
+    pub ls: Option<LocalSchema>,
+    pub vs: VirtualSchema,
+}
+
+/**
+ * Skips `offset` tuples then stops after `limit` more, without ordering its input.
+ */
+#[derive(Debug, Clone)]
+pub struct OpLimit {
+    pub input: Op,
+    pub limit: Option<u64>,
+    pub offset: u64,
     pub ls: Option<LocalSchema>,
     pub vs: VirtualSchema,
-    pub cfg_name: Option<String>,
 }
 
 /**
@@ -141,7 +999,29 @@ pub struct OpSubqueryProj {
     pub vs: VirtualSchema,
 }
 
-fn _indent_str(s: &str) -> String {
+/**
+ * Appends derived columns (each a `ColRef::ComputedRef`) to the input tuples.
+ */
+#[derive(Debug, Clone)]
+pub struct OpCompute {
+    pub input: Op,
+    pub exprs: Vec<ColRef>,
+    pub ls: Option<LocalSchema>,
+    pub vs: VirtualSchema,
+}
+
+/**
+ * Produces no tuples. Keeps the subtree it replaced so the schema and column types stay known,
+ * but that subtree is never executed.
+ */
+#[derive(Debug, Clone)]
+pub struct OpEmpty {
+    pub input: Op,
+    pub ls: Option<LocalSchema>,
+    pub vs: VirtualSchema,
+}
+
+pub fn indent_str(s: &str) -> String {
     s.replace("\n", &format!("\n{:ident$}", "", ident = 8))
 }
 
@@ -150,11 +1030,15 @@ impl fmt::Display for Op {
         match self {
             Op::ScanOp(op) => write!(f, "{}", op),
             Op::JoinOp(op) => write!(f, "{}", op),
+            Op::CrossJoinOp(op) => write!(f, "{}", op),
             Op::FilterOp(op) => write!(f, "{}", op),
             Op::ProjectionOp(op) => write!(f, "{}", op),
             Op::AggGroupOp(op) => write!(f, "{}", op),
-            Op::SortLimitOp(op) => write!(f, "{}", op),
+            Op::SortOp(op) => write!(f, "{}", op),
+            Op::LimitOp(op) => write!(f, "{}", op),
             Op::SubqueryProjOp(op) => write!(f, "{}", op),
+            Op::ComputeOp(op) => write!(f, "{}", op),
+            Op::EmptyOp(op) => write!(f, "{}", op),
         }
     }
 }
@@ -164,11 +1048,74 @@ impl fmt::Display for ColRef {
         match self {
             ColRef::TableRef { table, column } => write!(f, "{}.{}", table, column),
             ColRef::AggregateRef { func, source } => write!(f, "{:?}({})", func, source),
+            ColRef::ComputedRef { expr } => write!(f, "{}", expr),
         }
     }
 }
 
-fn _fmt_colref_slice(slice: &[ColRef]) -> String {
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Literal::Integer(value) | Literal::Decimal(value) => write!(f, "{}", value),
+            Literal::Text(value) => write!(f, "'{}'", value),
+            Literal::Date(value) => write!(f, "DATE '{}'", value),
+        }
+    }
+}
+
+impl fmt::Display for ScalarExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScalarExpr::Column(colref) => write!(f, "{}", colref),
+            ScalarExpr::Literal(value) => write!(f, "{}", value),
+            ScalarExpr::Arith { op, left, right } => {
+                write!(f, "({} {} {})", left, op.symbol(), right)
+            }
+            ScalarExpr::Case {
+                branches,
+                else_result,
+            } => {
+                write!(f, "CASE")?;
+                for (condition, result) in branches {
+                    write!(f, " WHEN {} THEN {}", condition, result)?;
+                }
+                if let Some(else_result) = else_result {
+                    write!(f, " ELSE {}", else_result)?;
+                }
+                write!(f, " END")
+            }
+            ScalarExpr::Cast { expr, to } => write!(f, "CAST({} AS {:?})", expr, to),
+            ScalarExpr::Null => write!(f, "NULL"),
+        }
+    }
+}
+
+impl fmt::Display for ScalarCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScalarCondition::Compare { op, left, right } => write!(f, "{} {} {}", left, op, right),
+            ScalarCondition::IsNull { expr, negated } => {
+                write!(f, "{} IS {}NULL", expr, if *negated { "NOT " } else { "" })
+            }
+            ScalarCondition::And(conditions) => {
+                write!(f, "({})", _join_conditions(conditions, " AND "))
+            }
+            ScalarCondition::Or(conditions) => {
+                write!(f, "({})", _join_conditions(conditions, " OR "))
+            }
+        }
+    }
+}
+
+fn _join_conditions(conditions: &[ScalarCondition], separator: &str) -> String {
+    conditions
+        .iter()
+        .map(|c| format!("{}", c))
+        .collect::<Vec<String>>()
+        .join(separator)
+}
+
+pub fn fmt_colref_slice(slice: &[ColRef]) -> String {
     format!(
         "[{}]",
         slice
@@ -180,14 +1127,31 @@ fn _fmt_colref_slice(slice: &[ColRef]) -> String {
 }
 
 impl fmt::Display for OpJoin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keys = self
+            .build_join_attributes
+            .iter()
+            .zip(&self.probe_join_attributes)
+            .map(|(b, p)| format!("{} == {}", b, p))
+            .collect::<Vec<String>>()
+            .join(", ");
+        write!(
+            f,
+            "Join ({})\n(build)>{}\n(probe)>{}",
+            keys,
+            indent_str(&format!("{}", self.build)),
+            indent_str(&format!("{}", self.probe))
+        )
+    }
+}
+
+impl fmt::Display for OpCrossJoin {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "HashJoin ({} == {})\n(build)>{}\n(probe)>{}",
-            self.build_join_attribute,
-            self.probe_join_attribute,
-            _indent_str(&format!("{}", self.build)),
-            _indent_str(&format!("{}", self.probe))
+            "CrossJoin\n(build)>{}\n(probe)>{}",
+            indent_str(&format!("{}", self.build)),
+            indent_str(&format!("{}", self.probe))
         )
     }
 }
@@ -198,12 +1162,48 @@ impl fmt::Display for OpSubqueryProj {
     }
 }
 
+impl fmt::Display for FilterPredicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilterPredicate::Compare { op, value } => write!(f, "{} {}", op, value),
+            FilterPredicate::Match {
+                mode,
+                pattern,
+                negated,
+                case_insensitive,
+            } => write!(
+                f,
+                "{}{:?}{} '{}'",
+                if *negated { "NOT " } else { "" },
+                mode,
+                if *case_insensitive { " (ci)" } else { "" },
+                pattern
+            ),
+            FilterPredicate::IsNull { negated } => {
+                write!(f, "IS {}NULL", if *negated { "NOT " } else { "" })
+            }
+            FilterPredicate::CompareColumns { op, other } => write!(f, "{} {}", op, other),
+        }
+    }
+}
+
+impl OpFilter {
+    /**
+     * Every column the filter reads.
+     */
+    pub fn columns(&self) -> Vec<ColRef> {
+        let mut columns = vec![self.field.clone()];
+        columns.extend(self.predicate.other_columns());
+        columns
+    }
+}
+
 impl fmt::Display for OpFilter {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "Filter ({} {} {})\n{}",
-            self.field, self.op, self.value, self.input
+            "Filter ({} {})\n{}",
+            self.field, self.predicate, self.input
         )
     }
 }
@@ -212,32 +1212,72 @@ impl fmt::Display for OpAggGroup {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "AggGroup (grouping={}, agg_field={})\n{}",
-            _fmt_colref_slice(&self.grouping),
+            "AggGroup (grouping={}, agg_field={}{})\n{}",
+            fmt_colref_slice(&self.grouping),
             self.agg_field,
+            if self.combines_partials {
+                ", combining partials"
+            } else {
+                ""
+            },
             self.input
         )
     }
 }
 
-impl fmt::Display for OpSortLimit {
+impl fmt::Display for OrderColumn {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "SortLimit (order_by={}, limit={})\n{}",
-            _fmt_colref_slice(&self.order_columns),
-            self.limit,
+            "{} {:?} NULLS {}",
+            self.column,
+            self.order,
+            if self.nulls_first { "FIRST" } else { "LAST" }
+        )
+    }
+}
+
+pub fn fmt_order_slice(slice: &[OrderColumn]) -> String {
+    format!(
+        "[{}]",
+        slice
+            .iter()
+            .map(|x| format!("{}", x))
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+impl fmt::Display for OpSort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Sort (order_by={})\n{}",
+            fmt_order_slice(&self.order_columns),
             self.input
         )
     }
 }
 
+impl fmt::Display for OpLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.limit {
+            Some(limit) => write!(
+                f,
+                "Limit (limit={}, offset={})\n{}",
+                limit, self.offset, self.input
+            ),
+            None => write!(f, "Limit (offset={})\n{}", self.offset, self.input),
+        }
+    }
+}
+
 impl fmt::Display for OpProjection {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
             "Projection ({})\n{}",
-            _fmt_colref_slice(&self.projection),
+            fmt_colref_slice(&self.projection),
             self.input
         )
     }
@@ -248,3 +1288,20 @@ impl fmt::Display for OpScan {
         write!(f, "Scan (table={}, file={})", self.tab_name, self.file)
     }
 }
+
+impl fmt::Display for OpCompute {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Compute ({})\n{}",
+            fmt_colref_slice(&self.exprs),
+            self.input
+        )
+    }
+}
+
+impl fmt::Display for OpEmpty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Empty")
+    }
+}
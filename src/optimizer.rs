@@ -2,117 +2,14 @@ use std::collections::HashSet;
 
 use crate::ops::*;
 use crate::planner::LocalSchema;
-
-pub fn pushdown_filters(op: Op) -> Op {
-    match op {
-        Op::ScanOp(op_scan) => Op::ScanOp(op_scan),
-        Op::JoinOp(mut op_join) => {
-            op_join.build = pushdown_filters(op_join.build);
-            op_join.probe = pushdown_filters(op_join.probe);
-            Op::JoinOp(op_join)
-        }
-        Op::FilterOp(mut op_filter) => {
-            op_filter.input = pushdown_filters(op_filter.input);
-
-            match &op_filter.input {
-                Op::ScanOp(_) => Op::FilterOp(op_filter),
-                Op::FilterOp(_) => Op::FilterOp(op_filter),
-                Op::SubqueryProjOp(_) => Op::FilterOp(op_filter),
-                Op::AggGroupOp(_) => {
-                    panic!("Can't pushdown filters below agg group (depending on HAVING clause)")
-                }
-                Op::JoinOp(sub_op) => {
-                    if sub_op.build.virtual_schema().contains(&op_filter.field) {
-                        Op::JoinOp(Box::new(OpJoin {
-                            build: pushdown_filters(Op::FilterOp(Box::new(OpFilter {
-                                input: sub_op.build.clone(),
-                                op: op_filter.op,
-                                field: op_filter.field,
-                                value: op_filter.value,
-                                ls: sub_op.build.local_schema(),
-                                vs: sub_op.build.virtual_schema(),
-                                cfg_name: op_filter.cfg_name,
-                            }))),
-                            build_join_attribute: sub_op.build_join_attribute.clone(),
-                            probe: pushdown_filters(sub_op.probe.clone()),
-                            probe_join_attribute: sub_op.probe_join_attribute.clone(),
-                            ls: sub_op.ls.clone(),
-                            vs: sub_op.vs.clone(),
-                            cfg_name: sub_op.cfg_name.clone(),
-                        }))
-                    } else if sub_op.probe.virtual_schema().contains(&op_filter.field) {
-                        Op::JoinOp(Box::new(OpJoin {
-                            build: pushdown_filters(sub_op.build.clone()),
-                            build_join_attribute: sub_op.build_join_attribute.clone(),
-                            probe: pushdown_filters(Op::FilterOp(Box::new(OpFilter {
-                                input: sub_op.probe.clone(),
-                                op: op_filter.op,
-                                field: op_filter.field,
-                                value: op_filter.value,
-                                ls: sub_op.probe.local_schema(),
-                                vs: sub_op.probe.virtual_schema(),
-                                cfg_name: op_filter.cfg_name,
-                            }))),
-                            probe_join_attribute: sub_op.probe_join_attribute.clone(),
-                            ls: sub_op.ls.clone(),
-                            vs: sub_op.vs.clone(),
-                            cfg_name: sub_op.cfg_name.clone(),
-                        }))
-                    } else {
-                        Op::FilterOp(op_filter)
-                    }
-                }
-                Op::ProjectionOp(sub_op) => Op::ProjectionOp(Box::new(OpProjection {
-                    input: Op::FilterOp(Box::new(OpFilter {
-                        input: sub_op.input.clone(),
-                        op: op_filter.op,
-                        field: op_filter.field,
-                        value: op_filter.value,
-                        ls: sub_op.input.local_schema(),
-                        vs: sub_op.input.virtual_schema(),
-                        cfg_name: op_filter.cfg_name,
-                    })),
-                    projection: sub_op.projection.clone(),
-                    ls: sub_op.ls.clone(),
-                    vs: sub_op.vs.clone(),
-                    cfg_name: sub_op.cfg_name.clone(),
-                })),
-                Op::SortLimitOp(sub_op) => Op::SortLimitOp(Box::new(OpSortLimit {
-                    input: Op::FilterOp(Box::new(OpFilter {
-                        input: sub_op.input.clone(),
-                        op: op_filter.op,
-                        field: op_filter.field,
-                        value: op_filter.value,
-                        ls: sub_op.input.local_schema(),
-                        vs: sub_op.input.virtual_schema(),
-                        cfg_name: op_filter.cfg_name,
-                    })),
-                    order_columns: sub_op.order_columns.clone(),
-                    order: sub_op.order.clone(),
-                    limit: sub_op.limit,
-                    ls: sub_op.ls.clone(),
-                    vs: sub_op.vs.clone(),
-                    cfg_name: sub_op.cfg_name.clone(),
-                })),
-            }
-        }
-        Op::ProjectionOp(mut op_project) => {
-            op_project.input = pushdown_filters(op_project.input);
-            Op::ProjectionOp(op_project)
-        }
-        Op::AggGroupOp(mut op_agg_group) => {
-            op_agg_group.input = pushdown_filters(op_agg_group.input);
-            Op::AggGroupOp(op_agg_group)
-        }
-        Op::SortLimitOp(mut op_sort_limit) => {
-            op_sort_limit.input = pushdown_filters(op_sort_limit.input);
-            Op::SortLimitOp(op_sort_limit)
-        }
-        Op::SubqueryProjOp(op_subquery_proj) => Op::SubqueryProjOp(op_subquery_proj),
-    }
-}
-
-fn _coerce_projection(op: Op, target_projection: &[ColRef], force_order: bool) -> Op {
+use crate::trace::Trace;
+
+fn _coerce_projection(
+    op: Op,
+    target_projection: &[ColRef],
+    force_order: bool,
+    trace: &mut Trace,
+) -> Op {
     let op_projection = op.local_schema().unwrap().columns;
 
     if !force_order {
@@ -127,18 +24,41 @@ fn _coerce_projection(op: Op, target_projection: &[ColRef], force_order: bool) -
     }
 
     let vs = op.virtual_schema();
-    Op::ProjectionOp(Box::new(OpProjection {
+    let projection = Op::ProjectionOp(Box::new(OpProjection {
         input: op,
         projection: target_projection.to_vec(),
         ls: Option::Some(LocalSchema {
             columns: target_projection.to_vec(),
         }),
         vs,
-        cfg_name: Option::None,
-    }))
+    }));
+    if let Op::ProjectionOp(op_projection) = &projection {
+        trace.record_rewrite(
+            "local_project",
+            "add_projection",
+            &op_projection.input,
+            &projection,
+        );
+    }
+    projection
+}
+
+fn _with_order_columns(order_columns: &[OrderColumn], target_projection: &[ColRef]) -> Vec<ColRef> {
+    let mut requirements: Vec<ColRef> = order_columns.iter().map(|o| o.column.clone()).collect();
+    for col in target_projection {
+        if !requirements.contains(col) {
+            requirements.push(col.clone());
+        }
+    }
+    requirements
 }
 
-pub fn local_project(op: Op, target_projection: &[ColRef], force_order: bool) -> Op {
+pub fn local_project(
+    op: Op,
+    target_projection: &[ColRef],
+    force_order: bool,
+    trace: &mut Trace,
+) -> Op {
     /*
      * On the head identify the requirements needed by the sub-ops.
      * On the tail construct a local schema and apply a projection if nessesary.
@@ -155,16 +75,18 @@ pub fn local_project(op: Op, target_projection: &[ColRef], force_order: bool) ->
                 columns: op.vs.columns.clone(),
             });
 
-            _coerce_projection(Op::ScanOp(op), target_projection, force_order)
+            _coerce_projection(Op::ScanOp(op), target_projection, force_order, trace)
         }
         Op::JoinOp(mut op) => {
             let mut requirements = target_projection.to_vec();
 
             // Add the build and probe requirements and makre sure they're at the beginning
-            requirements.retain(|c| c != &op.build_join_attribute);
-            requirements.retain(|c| c != &op.probe_join_attribute);
-            requirements.insert(0, op.probe_join_attribute.clone());
-            requirements.insert(0, op.build_join_attribute.clone());
+            requirements.retain(|c| {
+                !op.build_join_attributes.contains(c) && !op.probe_join_attributes.contains(c)
+            });
+            let mut keys = op.build_join_attributes.clone();
+            keys.extend(op.probe_join_attributes.iter().cloned());
+            requirements.splice(0..0, keys);
 
             let mut buildreqs = Vec::new();
             let mut probereqs = Vec::new();
@@ -185,8 +107,8 @@ pub fn local_project(op: Op, target_projection: &[ColRef], force_order: bool) ->
             }
 
             // Order matters only in build case
-            op.build = local_project(op.build, &buildreqs, true);
-            op.probe = local_project(op.probe, &probereqs, false);
+            op.build = local_project(op.build, &buildreqs, true, trace);
+            op.probe = local_project(op.probe, &probereqs, false, trace);
 
             // Joins have a built-in project operator
             // They target the local schema
@@ -197,54 +119,132 @@ pub fn local_project(op: Op, target_projection: &[ColRef], force_order: bool) ->
 
             Op::JoinOp(op)
         }
+        Op::CrossJoinOp(mut op) => {
+            let build_vs = op.build.virtual_schema();
+            let (buildreqs, probereqs): (Vec<ColRef>, Vec<ColRef>) = target_projection
+                .iter()
+                .cloned()
+                .partition(|c| build_vs.contains(c));
+
+            op.build = local_project(op.build, &buildreqs, false, trace);
+            op.probe = local_project(op.probe, &probereqs, false, trace);
+
+            // Like joins, the output is projected in place
+            op.ls = Option::Some(LocalSchema {
+                columns: target_projection.to_vec(),
+            });
+
+            Op::CrossJoinOp(op)
+        }
         Op::FilterOp(mut op) => {
             let mut requirements = target_projection.to_vec();
-            if !requirements.contains(&op.field) {
-                requirements.push(op.field.clone());
+            for column in op.columns() {
+                if !requirements.contains(&column) {
+                    requirements.push(column);
+                }
             }
 
-            op.input = local_project(op.input, &requirements, false);
+            op.input = local_project(op.input, &requirements, false, trace);
 
             op.ls = op.input.local_schema();
 
-            _coerce_projection(Op::FilterOp(op), target_projection, force_order)
+            _coerce_projection(Op::FilterOp(op), target_projection, force_order, trace)
         }
         Op::ProjectionOp(mut op) => {
-            op.input = local_project(op.input, target_projection, false);
+            op.input = local_project(op.input, target_projection, false, trace);
 
-            _coerce_projection(op.input, target_projection, force_order)
+            _coerce_projection(op.input, target_projection, force_order, trace)
         }
         Op::AggGroupOp(mut op) => {
             let mut requirements = op.grouping.clone();
-
-            match &op.agg_field {
-                ColRef::AggregateRef { func: _, source } => {
-                    requirements.push(source.as_ref().clone());
-                }
-                _ => panic!("Agg field must be aggregate type"),
-            }
+            requirements.push(op.source());
 
             let mut ls = op.grouping.clone();
             ls.push(op.agg_field.clone());
             op.ls = Option::Some(LocalSchema { columns: ls });
 
-            op.input = local_project(op.input, &requirements, true);
+            op.input = local_project(op.input, &requirements, true, trace);
 
-            _coerce_projection(Op::AggGroupOp(op), target_projection, force_order)
+            _coerce_projection(Op::AggGroupOp(op), target_projection, force_order, trace)
         }
-        Op::SortLimitOp(mut op) => {
-            let mut requirements = op.order_columns.clone();
-            requirements.extend_from_slice(target_projection);
+        Op::SortOp(mut op) => {
+            let requirements = _with_order_columns(&op.order_columns, target_projection);
 
-            op.input = local_project(op.input, &requirements, false);
+            op.input = local_project(op.input, &requirements, false, trace);
             op.ls = op.input.local_schema();
 
-            _coerce_projection(Op::SortLimitOp(op), target_projection, force_order)
+            _coerce_projection(Op::SortOp(op), target_projection, force_order, trace)
         }
+        Op::LimitOp(mut op) => {
+            // Keep the order columns of a sort below so lowering can still fuse the two
+            let requirements = match &op.input {
+                Op::SortOp(sub_op) => _with_order_columns(&sub_op.order_columns, target_projection),
+                _ => target_projection.to_vec(),
+            };
+
+            op.input = local_project(op.input, &requirements, false, trace);
+            op.ls = op.input.local_schema();
+
+            _coerce_projection(Op::LimitOp(op), target_projection, force_order, trace)
+        }
+        Op::ComputeOp(mut op) => {
+            // Only compute what is needed above
+            let before = if trace.enabled() {
+                Option::Some(Op::ComputeOp(op.clone()))
+            } else {
+                Option::None
+            };
+            let computed = op.exprs.len();
+            op.exprs.retain(|e| target_projection.contains(e));
+            if op.exprs.is_empty() {
+                if let Some(before) = before {
+                    trace.record_rewrite("local_project", "drop_compute", &before, &op.input);
+                }
+                return local_project(op.input, target_projection, force_order, trace);
+            }
+            if let (Some(before), true) = (before, op.exprs.len() < computed) {
+                let after = Op::ComputeOp(op.clone());
+                trace.record_rewrite("local_project", "prune_compute", &before, &after);
+            }
 
-        Op::SubqueryProjOp(op) => {
-            _coerce_projection(Op::SubqueryProjOp(op), target_projection, force_order)
+            let mut requirements: Vec<ColRef> = target_projection
+                .iter()
+                .filter(|c| !op.exprs.contains(c))
+                .cloned()
+                .collect();
+            for expr in &op.exprs {
+                if let ColRef::ComputedRef { expr } = expr {
+                    for source in expr.source_columns() {
+                        if !requirements.contains(&source) {
+                            requirements.push(source);
+                        }
+                    }
+                }
+            }
+
+            op.input = local_project(op.input, &requirements, false, trace);
+
+            // Derived columns are appended after the input columns
+            let mut ls = op.input.local_schema().unwrap().columns;
+            ls.extend(op.exprs.iter().cloned());
+            op.ls = Option::Some(LocalSchema { columns: ls });
+
+            _coerce_projection(Op::ComputeOp(op), target_projection, force_order, trace)
+        }
+        Op::EmptyOp(mut op) => {
+            // No tuples flow, so any column order can be produced directly
+            op.ls = Option::Some(LocalSchema {
+                columns: target_projection.to_vec(),
+            });
+            Op::EmptyOp(op)
         }
+
+        Op::SubqueryProjOp(op) => _coerce_projection(
+            Op::SubqueryProjOp(op),
+            target_projection,
+            force_order,
+            trace,
+        ),
     };
 
     op
@@ -0,0 +1,339 @@
+use std::fmt;
+
+use crate::metadata::MetaType;
+use crate::ops::*;
+use crate::planner::LocalSchema;
+
+/**
+ * How a join matches its inputs. A sort-merge join needs both inputs ordered on the join keys
+ * but doesn't hold the build side in memory. A nested-loop join has no keys and pairs every
+ * build tuple with every probe tuple.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinAlgorithm {
+    Hash,
+    SortMerge,
+    NestedLoop,
+}
+
+/**
+ * What the cost model expects of an op: the tuples it produces, its own cost and the cost
+ * including all of its inputs.
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpCost {
+    pub rows: f64,
+    pub cost: f64,
+    pub total: f64,
+}
+
+/**
+ * An op as pythia executes it. Each one maps to a single pythia operator, except for renames.
+ */
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone)]
+pub enum PhysicalOp {
+    ScanOp(Box<PhysScan>),
+    JoinOp(Box<PhysJoin>),
+    FilterOp(Box<PhysFilter>),
+    ProjectionOp(Box<PhysProjection>),
+    AggregateOp(Box<PhysAggregate>),
+    SortOp(Box<PhysSort>),
+    TopNOp(Box<PhysTopN>),
+    LimitOp(Box<PhysLimit>),
+    ComputeOp(Box<PhysCompute>),
+    EmptyOp(Box<PhysEmpty>),
+    RenameOp(Box<PhysRename>),
+}
+
+#[derive(Debug, Clone)]
+pub struct PhysScan {
+    pub file: String,
+    pub filetype: String,
+    pub tab_name: String,
+    pub schema: Vec<MetaType>,
+    pub sorted_by: Vec<ColRef>,
+    pub ls: LocalSchema,
+    pub estimate: OpCost,
+    pub cfg_name: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PhysJoin {
+    pub algorithm: JoinAlgorithm,
+    pub build: PhysicalOp,
+    pub build_join_attributes: Vec<ColRef>,
+    pub probe: PhysicalOp,
+    pub probe_join_attributes: Vec<ColRef>,
+    pub ls: LocalSchema,
+    pub estimate: OpCost,
+    pub cfg_name: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PhysFilter {
+    pub input: PhysicalOp,
+    pub field: ColRef,
+    pub predicate: FilterPredicate,
+    pub ls: LocalSchema,
+    pub estimate: OpCost,
+    pub cfg_name: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PhysProjection {
+    pub input: PhysicalOp,
+    pub projection: Vec<ColRef>,
+    pub ls: LocalSchema,
+    pub estimate: OpCost,
+    pub cfg_name: Option<String>,
+}
+
+/**
+ * Hash-based grouping, the output is the grouping columns followed by the aggregate.
+ */
+#[derive(Debug, Clone)]
+pub struct PhysAggregate {
+    pub input: PhysicalOp,
+    pub grouping: Vec<ColRef>,
+    pub agg_field: ColRef,
+    pub combines_partials: bool,
+    pub ls: LocalSchema,
+    pub estimate: OpCost,
+    pub cfg_name: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PhysSort {
+    pub input: PhysicalOp,
+    pub order_columns: Vec<OrderColumn>,
+    pub ls: LocalSchema,
+    pub estimate: OpCost,
+    pub cfg_name: Option<String>,
+}
+
+/**
+ * Sort that only keeps the first `limit` tuples in a bounded heap.
+ */
+#[derive(Debug, Clone)]
+pub struct PhysTopN {
+    pub input: PhysicalOp,
+    pub order_columns: Vec<OrderColumn>,
+    pub limit: u64,
+    pub ls: LocalSchema,
+    pub estimate: OpCost,
+    pub cfg_name: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PhysLimit {
+    pub input: PhysicalOp,
+    pub limit: Option<u64>,
+    pub offset: u64,
+    pub ls: LocalSchema,
+    pub estimate: OpCost,
+    pub cfg_name: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PhysCompute {
+    pub input: PhysicalOp,
+    pub exprs: Vec<ColRef>,
+    pub ls: LocalSchema,
+    pub estimate: OpCost,
+    pub cfg_name: Option<String>,
+}
+
+/**
+ * Produces no tuples, `schema` holds the column types since there is no input to ask.
+ */
+#[derive(Debug, Clone)]
+pub struct PhysEmpty {
+    pub schema: Vec<MetaType>,
+    pub ls: LocalSchema,
+    pub estimate: OpCost,
+    pub cfg_name: Option<String>,
+}
+
+/**
+ * Gives the columns of a subquery the names the outer query uses. Tuples are positional, so
+ * this doesn't execute anything.
+ */
+#[derive(Debug, Clone)]
+pub struct PhysRename {
+    pub input: PhysicalOp,
+    pub ls: LocalSchema,
+}
+
+impl PhysicalOp {
+    pub fn inputs(&self) -> Vec<&PhysicalOp> {
+        match self {
+            PhysicalOp::ScanOp(_) | PhysicalOp::EmptyOp(_) => vec![],
+            PhysicalOp::JoinOp(op) => vec![&op.build, &op.probe],
+            PhysicalOp::FilterOp(op) => vec![&op.input],
+            PhysicalOp::ProjectionOp(op) => vec![&op.input],
+            PhysicalOp::AggregateOp(op) => vec![&op.input],
+            PhysicalOp::SortOp(op) => vec![&op.input],
+            PhysicalOp::TopNOp(op) => vec![&op.input],
+            PhysicalOp::LimitOp(op) => vec![&op.input],
+            PhysicalOp::ComputeOp(op) => vec![&op.input],
+            PhysicalOp::RenameOp(op) => vec![&op.input],
+        }
+    }
+
+    /**
+     * A rename costs nothing, so it reports the estimate of its input.
+     */
+    pub fn estimate(&self) -> OpCost {
+        match self {
+            PhysicalOp::ScanOp(op) => op.estimate,
+            PhysicalOp::JoinOp(op) => op.estimate,
+            PhysicalOp::FilterOp(op) => op.estimate,
+            PhysicalOp::ProjectionOp(op) => op.estimate,
+            PhysicalOp::AggregateOp(op) => op.estimate,
+            PhysicalOp::SortOp(op) => op.estimate,
+            PhysicalOp::TopNOp(op) => op.estimate,
+            PhysicalOp::LimitOp(op) => op.estimate,
+            PhysicalOp::ComputeOp(op) => op.estimate,
+            PhysicalOp::EmptyOp(op) => op.estimate,
+            PhysicalOp::RenameOp(op) => op.input.estimate(),
+        }
+    }
+
+    pub fn local_schema(&self) -> &LocalSchema {
+        match self {
+            PhysicalOp::ScanOp(op) => &op.ls,
+            PhysicalOp::JoinOp(op) => &op.ls,
+            PhysicalOp::FilterOp(op) => &op.ls,
+            PhysicalOp::ProjectionOp(op) => &op.ls,
+            PhysicalOp::AggregateOp(op) => &op.ls,
+            PhysicalOp::SortOp(op) => &op.ls,
+            PhysicalOp::TopNOp(op) => &op.ls,
+            PhysicalOp::LimitOp(op) => &op.ls,
+            PhysicalOp::ComputeOp(op) => &op.ls,
+            PhysicalOp::EmptyOp(op) => &op.ls,
+            PhysicalOp::RenameOp(op) => &op.ls,
+        }
+    }
+
+    /**
+     * Columns the output is known to be sorted on in ascending order, most significant first.
+     */
+    pub fn output_order(&self) -> Vec<ColRef> {
+        match self {
+            PhysicalOp::ScanOp(op) => op.sorted_by.clone(),
+            PhysicalOp::JoinOp(op) => match op.algorithm {
+                JoinAlgorithm::SortMerge => op.probe_join_attributes.clone(),
+                JoinAlgorithm::Hash | JoinAlgorithm::NestedLoop => vec![],
+            },
+            PhysicalOp::SortOp(op) => _ascending_prefix(&op.order_columns),
+            PhysicalOp::TopNOp(op) => _ascending_prefix(&op.order_columns),
+            PhysicalOp::RenameOp(op) => {
+                let inner = &op.input.local_schema().columns;
+                op.input
+                    .output_order()
+                    .iter()
+                    .map_while(|c| inner.iter().position(|i| i == c))
+                    .map(|idx| op.ls.columns[idx].clone())
+                    .collect()
+            }
+            PhysicalOp::FilterOp(op) => op.input.output_order(),
+            PhysicalOp::ProjectionOp(op) => op.input.output_order(),
+            PhysicalOp::LimitOp(op) => op.input.output_order(),
+            PhysicalOp::ComputeOp(op) => op.input.output_order(),
+            PhysicalOp::AggregateOp(_) | PhysicalOp::EmptyOp(_) => vec![],
+        }
+    }
+}
+
+fn _ascending_prefix(order_columns: &[OrderColumn]) -> Vec<ColRef> {
+    order_columns
+        .iter()
+        .take_while(|c| c.order == Order::ASC)
+        .map(|c| c.column.clone())
+        .collect()
+}
+
+impl PhysicalOp {
+    fn _header(&self) -> String {
+        match self {
+            PhysicalOp::ScanOp(op) => format!("Scan (table={}, file={})", op.tab_name, op.file),
+            PhysicalOp::JoinOp(op) => {
+                let keys = op
+                    .build_join_attributes
+                    .iter()
+                    .zip(&op.probe_join_attributes)
+                    .map(|(b, p)| format!("{} == {}", b, p))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                match op.algorithm {
+                    JoinAlgorithm::Hash => format!("HashJoin ({})", keys),
+                    JoinAlgorithm::SortMerge => format!("MergeJoin ({})", keys),
+                    JoinAlgorithm::NestedLoop => "NestedLoopJoin (cross product)".to_string(),
+                }
+            }
+            PhysicalOp::FilterOp(op) => format!("Filter ({} {})", op.field, op.predicate),
+            PhysicalOp::ProjectionOp(op) => {
+                format!("Projection ({})", fmt_colref_slice(&op.projection))
+            }
+            PhysicalOp::AggregateOp(op) => format!(
+                "HashAggregate (grouping={}, agg_field={}{})",
+                fmt_colref_slice(&op.grouping),
+                op.agg_field,
+                if op.combines_partials {
+                    ", combining partials"
+                } else {
+                    ""
+                }
+            ),
+            PhysicalOp::SortOp(op) => {
+                format!("Sort (order_by={})", fmt_order_slice(&op.order_columns))
+            }
+            PhysicalOp::TopNOp(op) => format!(
+                "TopN (order_by={}, limit={})",
+                fmt_order_slice(&op.order_columns),
+                op.limit
+            ),
+            PhysicalOp::LimitOp(op) => match op.limit {
+                Some(limit) => format!("Limit (limit={}, offset={})", limit, op.offset),
+                None => format!("Limit (offset={})", op.offset),
+            },
+            PhysicalOp::ComputeOp(op) => format!("Compute ({})", fmt_colref_slice(&op.exprs)),
+            PhysicalOp::EmptyOp(_) => "Empty".to_string(),
+            PhysicalOp::RenameOp(op) => op.input._header(),
+        }
+    }
+}
+
+impl fmt::Display for PhysicalOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let PhysicalOp::RenameOp(op) = self {
+            return write!(f, "{}", op.input);
+        }
+
+        let estimate = self.estimate();
+        write!(
+            f,
+            "{} [rows={:.0}, cost={:.0}, total={:.0}]",
+            self._header(),
+            estimate.rows,
+            estimate.cost,
+            estimate.total
+        )?;
+
+        match self {
+            PhysicalOp::JoinOp(op) => write!(
+                f,
+                "\n(build)>{}\n(probe)>{}",
+                indent_str(&format!("{}", op.build)),
+                indent_str(&format!("{}", op.probe))
+            ),
+            _ => {
+                for input in self.inputs() {
+                    write!(f, "\n{}", input)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
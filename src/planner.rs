@@ -1,16 +1,23 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::rc::Rc;
 
 use sqlparser::ast::*;
 
 use crate::agg_grouping::*;
+use crate::compute::*;
 use crate::metadata::*;
 use crate::ops::*;
 use crate::optimizer;
 use crate::projection::*;
+use crate::resolve::NameResolver;
+use crate::rules::{RuleSet, Stage};
 use crate::selection::*;
 use crate::sort_limit::*;
+use crate::stats;
+use crate::trace::Trace;
+use crate::typecheck;
 
 impl From<&[Ident]> for ColRef {
     fn from(idents: &[Ident]) -> ColRef {
@@ -35,7 +42,7 @@ impl VirtualSchema {
         self.columns.contains(colref)
     }
 
-    fn from_meta_table(meta_schema: &MetaTableDef, table_alias: &str) -> VirtualSchema {
+    pub fn from_meta_table(meta_schema: &MetaTableDef, table_alias: &str) -> VirtualSchema {
         let mut columns = Vec::new();
 
         for column in &meta_schema.schema.columns {
@@ -52,7 +59,7 @@ impl VirtualSchema {
         VirtualSchema { columns }
     }
 
-    fn cat(schema1: &VirtualSchema, schema2: &VirtualSchema) -> VirtualSchema {
+    pub fn cat(schema1: &VirtualSchema, schema2: &VirtualSchema) -> VirtualSchema {
         let mut columns = schema1.columns.clone();
         columns.append(&mut schema2.columns.clone());
 
@@ -125,6 +132,20 @@ impl EqualityFactSet {
         }
     }
 
+    /**
+     * The other columns known to be equal to `colref`, in a stable order.
+     */
+    pub fn equivalents(&self, colref: &ColRef) -> Vec<ColRef> {
+        let mut out: Vec<ColRef> = self
+            .sets
+            .iter()
+            .filter(|set| set.contains(colref))
+            .flat_map(|set| set.iter().filter(|c| *c != colref).cloned())
+            .collect();
+        out.sort_by_key(|c| c.to_string());
+        out
+    }
+
     pub fn are_equal(&self, c1: &ColRef, c2: &ColRef) -> bool {
         for set in &self.sets {
             if set.contains(c1) && set.contains(c2) {
@@ -154,18 +175,41 @@ fn make_scan(table: &str, alias: &str, meta: &Metadata) -> OpScan {
     let table_meta = meta
         .tables
         .iter()
-        .filter(|t| t.name == table)
-        .last()
-        .expect(&format!("Can't find metadata entry for table {}", table));
+        .rfind(|t| t.name == table)
+        .unwrap_or_else(|| panic!("Can't find metadata entry for table {}", table));
+    let colrefs = |columns: &[String]| -> Vec<ColRef> {
+        columns
+            .iter()
+            .map(|column| ColRef::TableRef {
+                table: alias.to_string(),
+                column: column.clone(),
+            })
+            .collect()
+    };
 
     OpScan {
         tab_name: table.to_string(),
         file: table_meta.file.to_string(),
         filetype: table_meta.filetype.to_string(),
         schema: table_meta.schema.columns.iter().map(|c| c.1).collect(),
+        nullable: table_meta
+            .schema
+            .columns
+            .iter()
+            .map(|c| table_meta.schema.nullable.contains(&c.0))
+            .collect(),
+        sorted_by: colrefs(&table_meta.sorted_by),
+        primary_key: colrefs(&table_meta.primary_key),
+        foreign_keys: table_meta
+            .foreign_keys
+            .iter()
+            .map(|fk| ForeignKey {
+                columns: colrefs(&fk.columns),
+                references: fk.references.clone(),
+            })
+            .collect(),
         ls: Option::None,
         vs: VirtualSchema::from_meta_table(table_meta, alias),
-        cfg_name: Option::None,
     }
 }
 
@@ -181,8 +225,8 @@ fn plan_joins(
 
     #[derive(Hash, PartialEq, Eq, Debug)]
     struct Edge {
-        left_colref: ColRef,
-        right_colref: ColRef,
+        left_colrefs: Vec<ColRef>,
+        right_colrefs: Vec<ColRef>,
         left: Rc<Vertex>,
         right: Rc<Vertex>,
     }
@@ -194,24 +238,6 @@ fn plan_joins(
         }));
     }
 
-    // Handle simple case first
-    if v.len() == 1 {
-        let single_tab_vtx = v.iter().next().unwrap();
-        let single_tab = &from_namespace[&single_tab_vtx.alias];
-
-        return match single_tab {
-            TableSource::Direct(table_name) => (
-                Op::ScanOp(Box::new(make_scan(
-                    &table_name,
-                    &single_tab_vtx.alias,
-                    meta,
-                ))),
-                EqualityFactSet::new(),
-            ),
-            TableSource::Subquery(op) => (op.clone(), EqualityFactSet::new()),
-        };
-    }
-
     fn _find_vertex(v: &HashSet<Rc<Vertex>>, colref: &ColRef) -> Rc<Vertex> {
         let table_ref_name = match colref {
             ColRef::TableRef { table, column: _ } => table,
@@ -226,148 +252,163 @@ fn plan_joins(
         panic!("Failed to resolve table {:?}", colref);
     }
 
-    let mut e = HashSet::<Rc<Edge>>::new();
-    for potential_join in potential_joins {
-        let left = _find_vertex(&v, &potential_join.0);
-        let right = _find_vertex(&v, &potential_join.1);
-        e.insert(Rc::new(Edge {
-            left_colref: potential_join.0.clone(),
-            right_colref: potential_join.1.clone(),
-            left,
-            right,
-        }));
-    }
+    // All equalities between the same two tables make up one composite-key edge
+    let mut sorted_joins: Vec<&(ColRef, ColRef)> = potential_joins.iter().collect();
+    sorted_joins.sort_by_key(|(l, r)| (l.to_string(), r.to_string()));
+
+    let mut edges: Vec<Edge> = Vec::new();
+    for (l, r) in sorted_joins {
+        let left = _find_vertex(&v, l);
+        let right = _find_vertex(&v, r);
+        if left == right {
+            // Not a join, applied as a filter
+            continue;
+        }
 
-    fn _partial_hamiltonian_path(
-        v: &HashSet<Rc<Vertex>>,
-        e: &HashSet<Rc<Edge>>,
-        visited: &HashSet<Rc<Vertex>>,
-        current: &Rc<Vertex>,
-    ) -> Option<Vec<Rc<Edge>>> {
-        if v.len() == visited.len() {
-            return Option::Some(Vec::new());
+        match edges
+            .iter_mut()
+            .find(|e| (e.left == left && e.right == right) || (e.left == right && e.right == left))
+        {
+            Some(edge) if edge.left == left => {
+                edge.left_colrefs.push(l.clone());
+                edge.right_colrefs.push(r.clone());
+            }
+            Some(edge) => {
+                edge.left_colrefs.push(r.clone());
+                edge.right_colrefs.push(l.clone());
+            }
+            None => edges.push(Edge {
+                left_colrefs: vec![l.clone()],
+                right_colrefs: vec![r.clone()],
+                left,
+                right,
+            }),
         }
+    }
+    // Kept in the sorted order so the same query always gets the same join tree
+    let e: Vec<Rc<Edge>> = edges.into_iter().map(Rc::new).collect();
+
+    /**
+     * The spanning tree edges leading away from each vertex.
+     */
+    type Children = HashMap<Rc<Vertex>, Vec<Rc<Edge>>>;
+
+    /**
+     * Joins the tables of the spanning tree below `vertex`, each child subtree probing the
+     * tables joined so far.
+     */
+    fn associate_join(
+        vertex: &Rc<Vertex>,
+        children: &Children,
+        table_namespace: &HashMap<String, TableSource>,
+        meta: &Metadata,
+    ) -> Op {
+        let mut build_op = match &table_namespace[&vertex.alias] {
+            TableSource::Direct(table_name) => {
+                Op::ScanOp(Box::new(make_scan(table_name, &vertex.alias, meta)))
+            }
+            TableSource::Subquery(op) => op.clone(),
+        };
 
-        for edge in e {
-            let next = if &edge.left == current && !visited.contains(&edge.right) {
-                &edge.right
-            } else if &edge.right == current && !visited.contains(&edge.left) {
-                &edge.left
+        for edge in children.get(vertex).into_iter().flatten() {
+            let (build_crefs, probe_crefs, child) = if vertex == &edge.left {
+                (&edge.left_colrefs, &edge.right_colrefs, &edge.right)
             } else {
-                continue;
+                (&edge.right_colrefs, &edge.left_colrefs, &edge.left)
             };
 
-            let mut next_visited = visited.clone();
-            next_visited.insert(Rc::clone(next));
-
-            let future = _partial_hamiltonian_path(v, e, &next_visited, &next);
-            if let Some(mut path) = future {
-                path.push(Rc::clone(edge));
-                return Option::Some(path);
-            }
+            let probe_op = associate_join(child, children, table_namespace, meta);
+            let vs = VirtualSchema::cat(&build_op.virtual_schema(), &probe_op.virtual_schema());
+            build_op = Op::JoinOp(Box::new(OpJoin {
+                probe: probe_op,
+                probe_join_attributes: probe_crefs.clone(),
+                build: build_op,
+                build_join_attributes: build_crefs.clone(),
+                ls: Option::None,
+                vs,
+            }));
         }
 
-        Option::None
+        build_op
     }
 
-    fn _hamiltonian_path(
-        v: &HashSet<Rc<Vertex>>,
-        e: &HashSet<Rc<Edge>>,
-        visited: &HashSet<Rc<Vertex>>,
-    ) -> Option<(Vec<Rc<Edge>>, Rc<Vertex>)> {
-        if v.len() == visited.len() {
-            return Option::None;
-        }
+    /**
+     * Breadth-first spanning trees of all connected components and their roots. Edges left
+     * out are applied as filters.
+     */
+    fn _spanning_forest(v: &HashSet<Rc<Vertex>>, e: &[Rc<Edge>]) -> (Vec<Rc<Vertex>>, Children) {
+        let mut vertices: Vec<&Rc<Vertex>> = v.iter().collect();
+        vertices.sort_by(|a, b| a.alias.cmp(&b.alias));
 
-        for starting_vertex in v {
-            let mut visited = HashSet::<Rc<Vertex>>::new();
-            visited.insert(Rc::clone(starting_vertex));
+        let mut roots = Vec::new();
+        let mut children = Children::new();
+        let mut reached = HashSet::<Rc<Vertex>>::new();
 
-            if let Some(mut path) = _partial_hamiltonian_path(v, e, &visited, starting_vertex) {
-                path.reverse(); // We construct on the tail
-                return Option::Some((path, Rc::clone(starting_vertex)));
+        for root in vertices {
+            if !reached.insert(Rc::clone(root)) {
+                continue;
+            }
+            roots.push(Rc::clone(root));
+
+            let mut queue = VecDeque::from(vec![Rc::clone(root)]);
+            while let Some(vertex) = queue.pop_front() {
+                for edge in e {
+                    let next = if edge.left == vertex {
+                        &edge.right
+                    } else if edge.right == vertex {
+                        &edge.left
+                    } else {
+                        continue;
+                    };
+
+                    if reached.insert(Rc::clone(next)) {
+                        children
+                            .entry(Rc::clone(&vertex))
+                            .or_default()
+                            .push(Rc::clone(edge));
+                        queue.push_back(Rc::clone(next));
+                    }
+                }
             }
         }
 
-        Option::None
+        (roots, children)
     }
 
-    let (path, start) = _hamiltonian_path(&v, &e, &HashSet::new())
-        .expect("No way to represent query with equijoins!");
-
-    fn associate_join(
-        start_vertex: &Rc<Vertex>,
-        current_edge: &Rc<Edge>,
-        future: &[Rc<Edge>],
-        table_namespace: &HashMap<String, TableSource>,
-        meta: &Metadata,
-    ) -> Op {
-        let (start_cref, next_cref, next_vertex) = if start_vertex == &current_edge.left {
-            (
-                &current_edge.left_colref,
-                &current_edge.right_colref,
-                &current_edge.right,
-            )
-        } else {
-            (
-                &current_edge.right_colref,
-                &current_edge.left_colref,
-                &current_edge.left,
-            )
-        };
-
-        let probe_op = if future.is_empty() {
-            let next_table = &table_namespace[&next_vertex.alias];
+    let mut equality_set = EqualityFactSet::new();
 
-            match next_table {
-                TableSource::Direct(next_table_name) => Op::ScanOp(Box::new(make_scan(
-                    &next_table_name,
-                    &next_vertex.alias,
-                    meta,
-                ))),
-                TableSource::Subquery(op) => op.clone(),
-            }
-        } else {
-            associate_join(
-                &next_vertex,
-                &future[0],
-                &future[1..],
-                table_namespace,
-                meta,
-            )
-        };
+    let (roots, children) = _spanning_forest(&v, &e);
+    for edge in children.values().flatten() {
+        for (left, right) in edge.left_colrefs.iter().zip(&edge.right_colrefs) {
+            equality_set.insert_rule(left, right);
+        }
+    }
 
-        let start_table = &table_namespace[&start_vertex.alias];
-        let build_op = match start_table {
-            TableSource::Direct(start_table_name) => Op::ScanOp(Box::new(make_scan(
-                &start_table_name,
-                &start_vertex.alias,
-                meta,
-            ))),
-            TableSource::Subquery(op) => op.clone(),
-        };
+    let mut component_ops: Vec<Op> = roots
+        .iter()
+        .map(|root| associate_join(root, &children, from_namespace, meta))
+        .collect();
+    if component_ops.is_empty() {
+        panic!("Queries without a FROM clause are not supported");
+    }
 
-        let vs = VirtualSchema::cat(&build_op.virtual_schema(), &probe_op.virtual_schema());
-        Op::JoinOp(Box::new(OpJoin {
-            probe: probe_op,
-            probe_join_attribute: next_cref.clone(),
-            build: build_op,
-            build_join_attribute: start_cref.clone(),
+    // Tables with no equality between them are combined last, as cross products with the
+    // smaller side buffered
+    let estimate = |op: &Op| stats::estimate_rows(op, meta);
+    component_ops.sort_by(|a, b| estimate(b).partial_cmp(&estimate(a)).unwrap());
+
+    let mut root_op = component_ops.remove(0);
+    for op in component_ops {
+        let vs = VirtualSchema::cat(&op.virtual_schema(), &root_op.virtual_schema());
+        root_op = Op::CrossJoinOp(Box::new(OpCrossJoin {
+            build: op,
+            probe: root_op,
             ls: Option::None,
             vs,
-            cfg_name: Option::None,
-        }))
-    }
-
-    let mut equality_set = EqualityFactSet::new();
-    for edge in &path {
-        equality_set.insert_rule(&edge.left_colref, &edge.right_colref);
+        }));
     }
 
-    (
-        associate_join(&start, &path[0], &path[1..], from_namespace, meta),
-        equality_set,
-    )
+    (root_op, equality_set)
 }
 
 fn _correct_subquery_schema(op: Op, table_alias: &str, column_names: &[String]) -> Op {
@@ -395,7 +436,68 @@ fn _correct_subquery_schema(op: Op, table_alias: &str, column_names: &[String])
     Op::SubqueryProjOp(Box::new(OpSubqueryProj { input: op, vs, ls }))
 }
 
-pub fn plan(query: &Query, meta: &Metadata) -> (Op, Vec<String>) {
+/**
+ * Registers a FROM-clause table or subquery under its alias, returning the alias.
+ */
+fn _add_table_factor(
+    relation: &TableFactor,
+    meta: &Metadata,
+    rules: &RuleSet,
+    trace: &mut Trace,
+    table_namespace: &mut HashMap<String, TableSource>,
+    from_order: &mut Vec<String>,
+) -> String {
+    let (alias, source) = if let TableFactor::Table {
+        name,
+        alias,
+        args: _,
+        with_hints: _,
+    } = relation
+    {
+        let name = unwrap_table_name(&name.0);
+        let alias = if let Some(alias) = alias {
+            alias.name.value.to_string()
+        } else {
+            name.clone()
+        };
+
+        (alias, TableSource::Direct(name))
+    } else if let TableFactor::Derived {
+        lateral: _,
+        subquery,
+        alias,
+    } = relation
+    {
+        let alias = alias
+            .clone()
+            .expect("Subquery must have an alias!")
+            .name
+            .value
+            .to_string();
+
+        let (mut op, subquery_column_aliases) = plan(subquery, meta, rules, trace);
+        op = _correct_subquery_schema(op, &alias, &subquery_column_aliases);
+
+        (alias, TableSource::Subquery(op))
+    } else {
+        panic!("Not a table");
+    };
+
+    from_order.push(alias.clone());
+    table_namespace.insert(alias.clone(), source);
+    alias
+}
+
+/**
+ * Builds the logical plan of a query and the names of its output columns. How each op is
+ * executed is left to `memo::optimize`.
+ */
+pub fn plan(
+    query: &Query,
+    meta: &Metadata,
+    rules: &RuleSet,
+    trace: &mut Trace,
+) -> (Op, Vec<String>) {
     let setexpr = &query.body;
     let select = match setexpr {
         SetExpr::Select(select) => select,
@@ -405,46 +507,69 @@ pub fn plan(query: &Query, meta: &Metadata) -> (Op, Vec<String>) {
     let from = &select.from;
 
     let mut table_namespace = HashMap::<String, TableSource>::new();
+    let mut from_order = Vec::<String>::new();
+
+    let mut join_conditions = Vec::<Expr>::new();
+    let mut nullable_aliases = Vec::<String>::new();
 
     for table in from {
-        let relation = &table.relation;
-        if let TableFactor::Table {
-            name,
-            alias,
-            args: _,
-            with_hints: _,
-        } = relation
-        {
-            let name = unwrap_table_name(&name.0);
-            let alias = if let Some(alias) = alias {
-                alias.name.value.to_string()
-            } else {
-                name.clone()
+        let mut item_aliases = vec![_add_table_factor(
+            &table.relation,
+            meta,
+            rules,
+            trace,
+            &mut table_namespace,
+            &mut from_order,
+        )];
+
+        for join in &table.joins {
+            let alias = _add_table_factor(
+                &join.relation,
+                meta,
+                rules,
+                trace,
+                &mut table_namespace,
+                &mut from_order,
+            );
+
+            // Outer joins are only planned once WHERE is known to discard their NULL padding
+            let constraint = match &join.join_operator {
+                JoinOperator::Inner(constraint) => constraint,
+                JoinOperator::LeftOuter(constraint) => {
+                    nullable_aliases.push(alias.clone());
+                    constraint
+                }
+                JoinOperator::RightOuter(constraint) => {
+                    nullable_aliases.extend(item_aliases.iter().cloned());
+                    constraint
+                }
+                JoinOperator::FullOuter(constraint) => {
+                    nullable_aliases.extend(item_aliases.iter().cloned());
+                    nullable_aliases.push(alias.clone());
+                    constraint
+                }
+                JoinOperator::CrossJoin => &JoinConstraint::None,
+                _ => panic!("Unsupported join type {}", join),
             };
 
-            table_namespace.insert(alias, TableSource::Direct(name));
-        } else if let TableFactor::Derived {
-            lateral: _,
-            subquery,
-            alias,
-        } = relation
-        {
-            let alias = alias
-                .clone()
-                .expect("Subquery must have an alias!")
-                .name
-                .value
-                .to_string();
-
-            let (mut op, subquery_column_aliases) = plan(subquery, meta);
-            op = _correct_subquery_schema(op, &alias, &subquery_column_aliases);
+            match constraint {
+                JoinConstraint::On(expr) => join_conditions.push(expr.clone()),
+                JoinConstraint::None => {}
+                _ => panic!("Unsupported join constraint in {}, use ON", join),
+            }
 
-            table_namespace.insert(alias, TableSource::Subquery(op));
-        } else {
-            panic!("Not a table");
+            item_aliases.push(alias);
         }
     }
 
+    let resolver = NameResolver::from_namespace(&table_namespace, &from_order, meta);
+    let query = resolver.resolve_query(query);
+    let query = &query;
+    let select = match &query.body {
+        SetExpr::Select(select) => select,
+        _ => unreachable!(),
+    };
+
     let selection: Selection = if let Some(filter) = &select.selection {
         filter.into()
     } else {
@@ -452,11 +577,27 @@ pub fn plan(query: &Query, meta: &Metadata) -> (Op, Vec<String>) {
     };
     let selection = selection.normalized();
 
+    for alias in &nullable_aliases {
+        if !selection.rejects_nulls_from(alias) {
+            panic!(
+                "Outer joins are only supported when WHERE rejects NULLs from {}",
+                alias
+            );
+        }
+    }
+
+    // With the outer joins turned into inner joins, ON is just more conjuncts
+    let mut conjuncts = vec![selection];
+    for condition in &join_conditions {
+        conjuncts.push((&resolver.resolve_expr(condition)).into());
+    }
+    let selection = Selection::And(conjuncts).normalized();
+
     let projection: Projection = (&select.projection).into();
 
-    let agg_grouping = AggGrouping::from_query(&select, &projection);
+    let agg_grouping = AggGrouping::from_query(select, &projection);
 
-    let sort_limit: SortLimit = query.into();
+    let sort_limit = SortLimit::from_query(query, &projection);
 
     let potential_equijoins = selection.potential_equijoins();
 
@@ -464,14 +605,23 @@ pub fn plan(query: &Query, meta: &Metadata) -> (Op, Vec<String>) {
 
     let mut root_op = op;
 
+    root_op = Compute::pre_selection(&selection).apply_compute_ops(root_op);
+    let selection = selection
+        .simplified(&root_op)
+        .with_transitive_predicates(&equality_set)
+        .simplified(&root_op);
     root_op = selection.apply_filter_ops(root_op, &equality_set);
-    root_op = optimizer::pushdown_filters(root_op);
 
+    root_op = Compute::pre_aggregation(&projection).apply_compute_ops(root_op);
     root_op = agg_grouping.apply_agg_grouping_ops(root_op);
+    root_op = Compute::post_aggregation(&projection).apply_compute_ops(root_op);
     root_op = sort_limit.apply_sort_limit_ops(root_op);
+    root_op = typecheck::check_types(root_op);
+    root_op = rules.apply(root_op, Stage::Logical, trace);
 
     let output_projection = projection.needed_projection();
-    root_op = optimizer::local_project(root_op, &output_projection, true);
+    root_op = optimizer::local_project(root_op, &output_projection, true, trace);
+    root_op = rules.apply(root_op, Stage::Projected, trace);
 
     (root_op, projection.needed_projection_aliases())
 }
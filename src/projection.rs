@@ -1,5 +1,6 @@
 use sqlparser::ast::*;
 
+use crate::metadata::MetaType;
 use crate::ops::*;
 
 #[derive(Debug)]
@@ -20,7 +21,7 @@ pub fn convert_expr(expr: &Expr) -> ColRef {
             let arg = &function.args[0];
             let arg = match arg {
                 FunctionArg::Named { name: _, arg: _ } => panic!("Named arguments not supported!"),
-                FunctionArg::Unnamed(expr) => convert_expr(&expr),
+                FunctionArg::Unnamed(expr) => convert_expr(expr),
             };
 
             ColRef::AggregateRef {
@@ -28,7 +29,157 @@ pub fn convert_expr(expr: &Expr) -> ColRef {
                 source: Box::new(arg),
             }
         }
-        _ => panic!("Projection must be an identifier."),
+        Expr::Nested(subexpr) => convert_expr(subexpr),
+        Expr::BinaryOp { .. }
+        | Expr::UnaryOp { .. }
+        | Expr::Value(_)
+        | Expr::TypedString { .. }
+        | Expr::Case { .. }
+        | Expr::Cast { .. } => ColRef::ComputedRef {
+            expr: Box::new(convert_scalar_expr(expr)),
+        },
+        _ => panic!("Projection must be an identifier or expression."),
+    }
+}
+
+fn _parse_arith_op(op: &BinaryOperator) -> ArithOp {
+    match op {
+        BinaryOperator::Plus => ArithOp::Add,
+        BinaryOperator::Minus => ArithOp::Sub,
+        BinaryOperator::Multiply => ArithOp::Mul,
+        BinaryOperator::Divide => ArithOp::Div,
+        BinaryOperator::Modulus => ArithOp::Mod,
+        _ => panic!("Unsupported arithmetic operator {:?}", op),
+    }
+}
+
+fn _parse_compare_op(op: &BinaryOperator) -> Option<&'static str> {
+    match op {
+        BinaryOperator::Eq => Option::Some("=="),
+        BinaryOperator::NotEq => Option::Some("!="),
+        BinaryOperator::Lt => Option::Some("<"),
+        BinaryOperator::Gt => Option::Some(">"),
+        BinaryOperator::LtEq => Option::Some("<="),
+        BinaryOperator::GtEq => Option::Some(">="),
+        _ => Option::None,
+    }
+}
+
+fn _convert_data_type(data_type: &DataType) -> MetaType {
+    match data_type {
+        DataType::BigInt => MetaType::LONG,
+        DataType::Int | DataType::SmallInt => MetaType::INT,
+        DataType::Decimal(_, _) => MetaType::DEC,
+        DataType::Date => MetaType::DATE,
+        DataType::Char(Some(n)) => MetaType::CHAR(*n as u32),
+        DataType::Varchar(Some(n)) => MetaType::VARCHAR(*n as u32),
+        _ => panic!("Unsupported type {}", data_type),
+    }
+}
+
+fn _convert_condition(expr: &Expr) -> ScalarCondition {
+    match expr {
+        Expr::BinaryOp { left, op, right } => match op {
+            BinaryOperator::And => {
+                ScalarCondition::And(vec![_convert_condition(left), _convert_condition(right)])
+            }
+            BinaryOperator::Or => {
+                ScalarCondition::Or(vec![_convert_condition(left), _convert_condition(right)])
+            }
+            _ => ScalarCondition::Compare {
+                op: _parse_compare_op(op)
+                    .unwrap_or_else(|| panic!("Unsupported condition operator {:?}", op))
+                    .to_string(),
+                left: convert_scalar_expr(left),
+                right: convert_scalar_expr(right),
+            },
+        },
+        Expr::IsNull(subexpr) => ScalarCondition::IsNull {
+            expr: convert_scalar_expr(subexpr),
+            negated: false,
+        },
+        Expr::IsNotNull(subexpr) => ScalarCondition::IsNull {
+            expr: convert_scalar_expr(subexpr),
+            negated: true,
+        },
+        Expr::Nested(subexpr) => _convert_condition(subexpr),
+        _ => panic!("Unsupported condition {}", expr),
+    }
+}
+
+pub fn convert_literal(expr: &Expr) -> Literal {
+    match expr {
+        Expr::Value(v) => match v {
+            Value::Number(val_str, _) => Literal::from_number(val_str),
+            Value::SingleQuotedString(val_str) => Literal::Text(val_str.to_string()),
+            _ => panic!("Unsupported value type"),
+        },
+        Expr::TypedString { data_type, value } => match data_type {
+            DataType::Date => Literal::from_date(value),
+            _ => panic!("Unsupported typed literal {}", data_type),
+        },
+        _ => panic!("Not a literal: {:?}", expr),
+    }
+}
+
+pub fn convert_scalar_expr(expr: &Expr) -> ScalarExpr {
+    match expr {
+        Expr::BinaryOp { left, op, right } => ScalarExpr::Arith {
+            op: _parse_arith_op(op),
+            left: Box::new(convert_scalar_expr(left)),
+            right: Box::new(convert_scalar_expr(right)),
+        },
+        Expr::UnaryOp { op, expr } => match op {
+            UnaryOperator::Plus => convert_scalar_expr(expr),
+            UnaryOperator::Minus => ScalarExpr::Arith {
+                op: ArithOp::Sub,
+                left: Box::new(ScalarExpr::Literal(Literal::Integer("0".to_string()))),
+                right: Box::new(convert_scalar_expr(expr)),
+            },
+            _ => panic!("Unsupported unary operator {:?}", op),
+        },
+        Expr::Nested(subexpr) => convert_scalar_expr(subexpr),
+        Expr::Cast { expr, data_type } => ScalarExpr::Cast {
+            expr: Box::new(convert_scalar_expr(expr)),
+            to: _convert_data_type(data_type),
+        },
+        Expr::Case {
+            operand,
+            conditions,
+            results,
+            else_result,
+        } => {
+            let branches = conditions
+                .iter()
+                .zip(results)
+                .map(|(condition, result)| {
+                    // `CASE x WHEN v` is shorthand for `CASE WHEN x = v`
+                    let condition = match operand {
+                        Some(operand) => ScalarCondition::Compare {
+                            op: "==".to_string(),
+                            left: convert_scalar_expr(operand),
+                            right: convert_scalar_expr(condition),
+                        },
+                        None => _convert_condition(condition),
+                    };
+                    (condition, convert_scalar_expr(result))
+                })
+                .collect();
+
+            ScalarExpr::Case {
+                branches,
+                else_result: match else_result.as_deref() {
+                    None | Some(Expr::Value(Value::Null)) => Option::None,
+                    Some(else_result) => Option::Some(Box::new(convert_scalar_expr(else_result))),
+                },
+            }
+        }
+        Expr::Value(Value::Null) => ScalarExpr::Null,
+        Expr::Value(_) | Expr::TypedString { .. } => ScalarExpr::Literal(convert_literal(expr)),
+        _ => match convert_expr(expr) {
+            ColRef::ComputedRef { expr } => *expr,
+            colref => ScalarExpr::Column(colref),
+        },
     }
 }
 
@@ -38,8 +189,8 @@ fn _convert_item(item: &SelectItem) -> (ColRef, Option<String>) {
         SelectItem::ExprWithAlias { expr, alias } => {
             (convert_expr(expr), Option::Some(alias.value.clone()))
         }
-        SelectItem::QualifiedWildcard(_) => panic!("Wildcards are not supported"),
-        SelectItem::Wildcard => panic!("Wildcards are not supported"),
+        SelectItem::QualifiedWildcard(_) => panic!("Wildcards must be expanded before conversion"),
+        SelectItem::Wildcard => panic!("Wildcards must be expanded before conversion"),
     }
 }
 
@@ -50,7 +201,11 @@ impl From<&Vec<sqlparser::ast::SelectItem>> for Projection {
 
         for (i, item) in items.iter().enumerate() {
             let (internal_item, alias_optional) = _convert_item(item);
-            let alias = alias_optional.unwrap_or(format!("_{}", i));
+            // Plain columns keep their name, anything else is named by position
+            let alias = alias_optional.unwrap_or_else(|| match &internal_item {
+                ColRef::TableRef { table: _, column } => column.clone(),
+                _ => format!("_{}", i),
+            });
             projection_items.push(internal_item);
             projection_aliases.push(alias);
         }
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+
+use sqlparser::ast::*;
+
+use crate::metadata::Metadata;
+use crate::ops::ColRef;
+use crate::planner::{TableSource, VirtualSchema};
+
+/**
+ * Qualifies bare column names with the FROM-clause alias that provides them, so the rest of the
+ * planner only ever sees `alias.column`.
+ */
+#[derive(Debug)]
+pub struct NameResolver {
+    schemas: Vec<(String, VirtualSchema)>,
+}
+
+impl NameResolver {
+    /**
+     * `from_order` lists the FROM-clause aliases in the order they were written, which is the
+     * order wildcards expand in.
+     */
+    pub fn from_namespace(
+        table_namespace: &HashMap<String, TableSource>,
+        from_order: &[String],
+        meta: &Metadata,
+    ) -> NameResolver {
+        let mut schemas = Vec::new();
+
+        for alias in from_order {
+            let vs = match &table_namespace[alias] {
+                TableSource::Direct(table_name) => {
+                    let table_meta = meta
+                        .tables
+                        .iter()
+                        .rfind(|t| &t.name == table_name)
+                        .unwrap_or_else(|| {
+                            panic!("Can't find metadata entry for table {}", table_name)
+                        });
+                    VirtualSchema::from_meta_table(table_meta, alias)
+                }
+                TableSource::Subquery(op) => op.virtual_schema(),
+            };
+
+            schemas.push((alias.clone(), vs));
+        }
+
+        NameResolver { schemas }
+    }
+
+    fn resolve_column(&self, column: &Ident) -> Vec<Ident> {
+        let mut candidates: Vec<&String> = self
+            .schemas
+            .iter()
+            .filter(|(alias, vs)| {
+                vs.contains(&ColRef::TableRef {
+                    table: alias.clone(),
+                    column: column.value.clone(),
+                })
+            })
+            .map(|(alias, _)| alias)
+            .collect();
+        // Keep error messages stable regardless of FROM order
+        candidates.sort();
+
+        match candidates.len() {
+            0 => panic!("Column {} not found in any FROM table", column),
+            1 => {
+                let idents = vec![Ident::new(candidates[0]), column.clone()];
+                self._check_unique(&idents);
+                idents
+            }
+            _ => panic!(
+                "Column reference {} is ambiguous, it exists in {}",
+                column,
+                candidates
+                    .iter()
+                    .map(|c| c.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    /**
+     * A subquery's `SELECT *` can produce several columns with the same name, a reference to
+     * one of them can't tell which is meant.
+     */
+    fn _check_unique(&self, idents: &[Ident]) {
+        let (alias, column) = match idents {
+            [alias, column] => (&alias.value, &column.value),
+            _ => return,
+        };
+        let colref = ColRef::TableRef {
+            table: alias.clone(),
+            column: column.clone(),
+        };
+        let count = self
+            .schemas
+            .iter()
+            .filter(|(a, _)| a == alias)
+            .map(|(_, vs)| vs.columns.iter().filter(|c| **c == colref).count())
+            .sum::<usize>();
+        if count > 1 {
+            panic!(
+                "Column reference {} is ambiguous, {} produces {} columns named {}",
+                colref, alias, count, column
+            );
+        }
+    }
+
+    pub fn resolve_expr(&self, expr: &Expr) -> Expr {
+        let boxed = |e: &Expr| Box::new(self.resolve_expr(e));
+
+        match expr {
+            Expr::Identifier(ident) => Expr::CompoundIdentifier(self.resolve_column(ident)),
+            Expr::CompoundIdentifier(idents) => {
+                self._check_unique(idents);
+                expr.clone()
+            }
+            Expr::BinaryOp { left, op, right } => Expr::BinaryOp {
+                left: boxed(left),
+                op: op.clone(),
+                right: boxed(right),
+            },
+            Expr::UnaryOp { op, expr } => Expr::UnaryOp {
+                op: op.clone(),
+                expr: boxed(expr),
+            },
+            Expr::Nested(subexpr) => Expr::Nested(boxed(subexpr)),
+            Expr::IsNull(subexpr) => Expr::IsNull(boxed(subexpr)),
+            Expr::IsNotNull(subexpr) => Expr::IsNotNull(boxed(subexpr)),
+            Expr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => Expr::Between {
+                expr: boxed(expr),
+                negated: *negated,
+                low: boxed(low),
+                high: boxed(high),
+            },
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => Expr::InList {
+                expr: boxed(expr),
+                list: list.iter().map(|e| self.resolve_expr(e)).collect(),
+                negated: *negated,
+            },
+            Expr::Cast { expr, data_type } => Expr::Cast {
+                expr: boxed(expr),
+                data_type: data_type.clone(),
+            },
+            Expr::Case {
+                operand,
+                conditions,
+                results,
+                else_result,
+            } => Expr::Case {
+                operand: operand.as_ref().map(|e| boxed(e)),
+                conditions: conditions.iter().map(|e| self.resolve_expr(e)).collect(),
+                results: results.iter().map(|e| self.resolve_expr(e)).collect(),
+                else_result: else_result.as_ref().map(|e| boxed(e)),
+            },
+            Expr::Function(function) => {
+                let mut function = function.clone();
+                for arg in function.args.iter_mut() {
+                    match arg {
+                        FunctionArg::Named { name: _, arg } => *arg = self.resolve_expr(arg),
+                        FunctionArg::Unnamed(arg) => *arg = self.resolve_expr(arg),
+                    }
+                }
+                Expr::Function(function)
+            }
+            _ => expr.clone(),
+        }
+    }
+
+    fn expand_wildcard(&self, vs: &VirtualSchema) -> Vec<SelectItem> {
+        vs.columns
+            .iter()
+            .map(|c| match c {
+                ColRef::TableRef { table, column } => {
+                    let idents = vec![Ident::new(table), Ident::new(column)];
+                    self._check_unique(&idents);
+                    SelectItem::UnnamedExpr(Expr::CompoundIdentifier(idents))
+                }
+                _ => panic!("Can't expand non-table column {} in wildcard", c),
+            })
+            .collect()
+    }
+
+    fn resolve_item(&self, item: &SelectItem) -> Vec<SelectItem> {
+        match item {
+            SelectItem::UnnamedExpr(expr) => vec![SelectItem::UnnamedExpr(self.resolve_expr(expr))],
+            SelectItem::ExprWithAlias { expr, alias } => vec![SelectItem::ExprWithAlias {
+                expr: self.resolve_expr(expr),
+                alias: alias.clone(),
+            }],
+            SelectItem::Wildcard => self
+                .schemas
+                .iter()
+                .flat_map(|(_, vs)| self.expand_wildcard(vs))
+                .collect(),
+            SelectItem::QualifiedWildcard(name) => {
+                if name.0.len() != 1 {
+                    panic!("Invalid wildcard qualifier {}", name);
+                }
+                let alias = &name.0[0].value;
+
+                let (_, vs) = self
+                    .schemas
+                    .iter()
+                    .find(|(a, _)| a == alias)
+                    .unwrap_or_else(|| panic!("Wildcard table {} not found in FROM", alias));
+                self.expand_wildcard(vs)
+            }
+        }
+    }
+
+    /**
+     * Resolves every clause of the query except FROM, which defines the namespace itself.
+     * ORDER BY identifiers that name an output column are left for the sort planner.
+     */
+    pub fn resolve_query(&self, query: &Query) -> Query {
+        let mut query = query.clone();
+
+        let select = match &mut query.body {
+            SetExpr::Select(select) => select,
+            _ => panic!("Not a select"),
+        };
+
+        select.projection = select
+            .projection
+            .iter()
+            .flat_map(|item| self.resolve_item(item))
+            .collect();
+        select.selection = select.selection.as_ref().map(|e| self.resolve_expr(e));
+        select.group_by = select
+            .group_by
+            .iter()
+            .map(|e| self.resolve_expr(e))
+            .collect();
+        select.having = select.having.as_ref().map(|e| self.resolve_expr(e));
+
+        let output_aliases: Vec<String> = select
+            .projection
+            .iter()
+            .filter_map(|item| match item {
+                SelectItem::ExprWithAlias { expr: _, alias } => Option::Some(alias.value.clone()),
+                SelectItem::UnnamedExpr(Expr::CompoundIdentifier(idents)) => {
+                    idents.last().map(|i| i.value.clone())
+                }
+                _ => Option::None,
+            })
+            .collect();
+
+        for order_expr in query.order_by.iter_mut() {
+            if let Expr::Identifier(ident) = &order_expr.expr {
+                if output_aliases.contains(&ident.value) {
+                    continue;
+                }
+            }
+            order_expr.expr = self.resolve_expr(&order_expr.expr);
+        }
+
+        query
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::*;
+
+    #[test]
+    fn expands_qualified_wildcard() {
+        assert_plan(
+            "SELECT O.* FROM ORDERS O",
+            &["Scan (table=ORDERS, file=orders.tbl)"],
+        );
+    }
+
+    #[test]
+    fn resolves_unique_subquery_column() {
+        assert_plan(
+            "SELECT S.ZIP FROM (SELECT * FROM LINEITEM L, ORDERS O WHERE L.OKEY = O.OKEY) S",
+            &[
+                "Projection ([S.ZIP])",
+                "Join (L.OKEY == O.OKEY)",
+                "(build)>Scan (table=LINEITEM, file=lineitem.tbl)",
+                "(probe)>Scan (table=ORDERS, file=orders.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Column reference S.OKEY is ambiguous, S produces 2 columns named OKEY"
+    )]
+    fn rejects_duplicate_subquery_column() {
+        plan_sql("SELECT OKEY FROM (SELECT * FROM LINEITEM L, ORDERS O WHERE L.OKEY = O.OKEY) S");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Column reference S.OKEY is ambiguous, S produces 2 columns named OKEY"
+    )]
+    fn rejects_wildcard_over_duplicate_subquery_columns() {
+        plan_sql("SELECT * FROM (SELECT * FROM LINEITEM L, LI2 M WHERE L.OKEY = M.OKEY) S");
+    }
+}
@@ -0,0 +1,979 @@
+use crate::ops::*;
+use crate::planner::{LocalSchema, VirtualSchema};
+use crate::trace::Trace;
+
+/**
+ * When a rule runs. Most rules rewrite the plan as it is built, the others need to know which
+ * columns each op has to produce and run once projections are pushed down.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Logical,
+    Projected,
+}
+
+/**
+ * A rewrite of the logical plan. The driver offers every op to every enabled rule, and calls
+ * `rewrite` on the ops that `matches` accepts.
+ */
+pub trait Rule {
+    /**
+     * Name used to disable the rule from the command line.
+     */
+    fn name(&self) -> &'static str;
+
+    fn stage(&self) -> Stage {
+        Stage::Logical
+    }
+
+    fn matches(&self, op: &Op) -> bool;
+
+    /**
+     * Rewrites an op accepted by `matches` into an equivalent one. Must make progress, or the
+     * driver never reaches a fixpoint.
+     */
+    fn rewrite(&self, op: Op) -> Op;
+}
+
+/**
+ * The rules the optimizer applies, in the order they are tried on each op.
+ */
+pub struct RuleSet {
+    rules: Vec<Box<dyn Rule>>,
+}
+
+impl RuleSet {
+    fn all() -> Vec<Box<dyn Rule>> {
+        vec![
+            Box::new(FilterOverEmpty),
+            Box::new(FilterIntoJoin),
+            Box::new(FilterIntoCrossJoin),
+            Box::new(FilterPastProjection),
+            Box::new(FilterPastSort),
+            Box::new(FilterPastCompute),
+            Box::new(LimitPastProjection),
+            Box::new(LimitPastCompute),
+            Box::new(LimitIntoJoin),
+            Box::new(TopNIntoJoin),
+            Box::new(EagerAggregation),
+            Box::new(JoinElimination),
+        ]
+    }
+
+    pub fn names() -> Vec<&'static str> {
+        RuleSet::all().iter().map(|r| r.name()).collect()
+    }
+
+    /**
+     * Every rule except the named ones.
+     */
+    pub fn without(disabled: &[&str]) -> RuleSet {
+        RuleSet {
+            rules: RuleSet::all()
+                .into_iter()
+                .filter(|r| !disabled.contains(&r.name()))
+                .collect(),
+        }
+    }
+
+    /**
+     * Rewrites the plan with the rules of a stage until none of them matches any op anymore.
+     */
+    pub fn apply(&self, op: Op, stage: Stage, trace: &mut Trace) -> Op {
+        let mut op = op;
+        loop {
+            let mut changed = false;
+            op = self._apply_once(op, stage, &mut changed, trace);
+            if !changed {
+                return op;
+            }
+        }
+    }
+
+    fn _apply_once(&self, op: Op, stage: Stage, changed: &mut bool, trace: &mut Trace) -> Op {
+        let mut op = op;
+        if let Some(rule) = self
+            .rules
+            .iter()
+            .find(|r| r.stage() == stage && r.matches(&op))
+        {
+            let before = if trace.enabled() {
+                Option::Some(op.clone())
+            } else {
+                Option::None
+            };
+            op = rule.rewrite(op);
+            if let Some(before) = before {
+                trace.record_rewrite("rules", rule.name(), &before, &op);
+            }
+            *changed = true;
+        }
+
+        match op {
+            // Subqueries were already optimized when they were planned
+            Op::SubqueryProjOp(_) => op,
+            _ => op.map_inputs(|input| self._apply_once(input, stage, changed, trace)),
+        }
+    }
+}
+
+fn _produces_all(op: &Op, columns: &[ColRef]) -> bool {
+    let vs = op.virtual_schema();
+    columns.iter().all(|c| vs.contains(c))
+}
+
+fn _filter_input(op: &Op) -> Option<&Op> {
+    match op {
+        Op::FilterOp(op_filter) => Option::Some(&op_filter.input),
+        _ => Option::None,
+    }
+}
+
+/**
+ * Splits a filter into what it checks and its input.
+ */
+fn _take_filter(op: Op) -> (ColRef, FilterPredicate, Op) {
+    match op {
+        Op::FilterOp(op_filter) => {
+            let OpFilter {
+                input,
+                field,
+                predicate,
+                ..
+            } = *op_filter;
+            (field, predicate, input)
+        }
+        _ => unreachable!(),
+    }
+}
+
+/**
+ * Applies a filter taken apart by `_take_filter` to another input.
+ */
+fn _filter_over(field: ColRef, predicate: FilterPredicate, input: Op) -> Op {
+    Op::FilterOp(Box::new(OpFilter {
+        ls: input.local_schema(),
+        vs: input.virtual_schema(),
+        input,
+        field,
+        predicate,
+    }))
+}
+
+/**
+ * There is nothing left to filter below an empty op.
+ */
+struct FilterOverEmpty;
+
+impl Rule for FilterOverEmpty {
+    fn name(&self) -> &'static str {
+        "filter_over_empty"
+    }
+
+    fn matches(&self, op: &Op) -> bool {
+        matches!(_filter_input(op), Some(Op::EmptyOp(_)))
+    }
+
+    fn rewrite(&self, op: Op) -> Op {
+        _take_filter(op).2
+    }
+}
+
+/**
+ * Moves a filter into the join input that produces all the columns it reads.
+ */
+struct FilterIntoJoin;
+
+impl Rule for FilterIntoJoin {
+    fn name(&self) -> &'static str {
+        "filter_into_join"
+    }
+
+    fn matches(&self, op: &Op) -> bool {
+        match (op, _filter_input(op)) {
+            (Op::FilterOp(op_filter), Some(Op::JoinOp(op_join))) => {
+                _produces_all(&op_join.build, &op_filter.columns())
+                    || _produces_all(&op_join.probe, &op_filter.columns())
+            }
+            _ => false,
+        }
+    }
+
+    fn rewrite(&self, op: Op) -> Op {
+        let (field, predicate, input) = _take_filter(op);
+        let mut op_join = match input {
+            Op::JoinOp(op_join) => op_join,
+            _ => unreachable!(),
+        };
+
+        let mut columns = predicate.other_columns();
+        columns.push(field.clone());
+        if _produces_all(&op_join.build, &columns) {
+            op_join.build = _filter_over(field, predicate, op_join.build);
+        } else {
+            op_join.probe = _filter_over(field, predicate, op_join.probe);
+        }
+        Op::JoinOp(op_join)
+    }
+}
+
+/**
+ * Moves a filter into the cross product input that produces all the columns it reads.
+ */
+struct FilterIntoCrossJoin;
+
+impl Rule for FilterIntoCrossJoin {
+    fn name(&self) -> &'static str {
+        "filter_into_cross_join"
+    }
+
+    fn matches(&self, op: &Op) -> bool {
+        match (op, _filter_input(op)) {
+            (Op::FilterOp(op_filter), Some(Op::CrossJoinOp(op_cross))) => {
+                _produces_all(&op_cross.build, &op_filter.columns())
+                    || _produces_all(&op_cross.probe, &op_filter.columns())
+            }
+            _ => false,
+        }
+    }
+
+    fn rewrite(&self, op: Op) -> Op {
+        let (field, predicate, input) = _take_filter(op);
+        let mut op_cross = match input {
+            Op::CrossJoinOp(op_cross) => op_cross,
+            _ => unreachable!(),
+        };
+
+        let mut columns = predicate.other_columns();
+        columns.push(field.clone());
+        if _produces_all(&op_cross.build, &columns) {
+            op_cross.build = _filter_over(field, predicate, op_cross.build);
+        } else {
+            op_cross.probe = _filter_over(field, predicate, op_cross.probe);
+        }
+        Op::CrossJoinOp(op_cross)
+    }
+}
+
+/**
+ * Filters before projecting, a projection never changes the values a filter sees.
+ */
+struct FilterPastProjection;
+
+impl Rule for FilterPastProjection {
+    fn name(&self) -> &'static str {
+        "filter_past_projection"
+    }
+
+    fn matches(&self, op: &Op) -> bool {
+        matches!(_filter_input(op), Some(Op::ProjectionOp(_)))
+    }
+
+    fn rewrite(&self, op: Op) -> Op {
+        let (field, predicate, input) = _take_filter(op);
+        let mut op_projection = match input {
+            Op::ProjectionOp(op_projection) => op_projection,
+            _ => unreachable!(),
+        };
+
+        op_projection.input = _filter_over(field, predicate, op_projection.input);
+        Op::ProjectionOp(op_projection)
+    }
+}
+
+/**
+ * Filters before sorting so fewer tuples are sorted.
+ */
+struct FilterPastSort;
+
+impl Rule for FilterPastSort {
+    fn name(&self) -> &'static str {
+        "filter_past_sort"
+    }
+
+    fn matches(&self, op: &Op) -> bool {
+        matches!(_filter_input(op), Some(Op::SortOp(_)))
+    }
+
+    fn rewrite(&self, op: Op) -> Op {
+        let (field, predicate, input) = _take_filter(op);
+        let mut op_sort = match input {
+            Op::SortOp(op_sort) => op_sort,
+            _ => unreachable!(),
+        };
+
+        op_sort.input = _filter_over(field, predicate, op_sort.input);
+        Op::SortOp(op_sort)
+    }
+}
+
+/**
+ * Filters before computing expressions, unless the filter reads one of them.
+ */
+struct FilterPastCompute;
+
+impl Rule for FilterPastCompute {
+    fn name(&self) -> &'static str {
+        "filter_past_compute"
+    }
+
+    fn matches(&self, op: &Op) -> bool {
+        match (op, _filter_input(op)) {
+            (Op::FilterOp(op_filter), Some(Op::ComputeOp(op_compute))) => !op_filter
+                .columns()
+                .iter()
+                .any(|c| op_compute.exprs.contains(c)),
+            _ => false,
+        }
+    }
+
+    fn rewrite(&self, op: Op) -> Op {
+        let (field, predicate, input) = _take_filter(op);
+        let mut op_compute = match input {
+            Op::ComputeOp(op_compute) => op_compute,
+            _ => unreachable!(),
+        };
+
+        op_compute.input = _filter_over(field, predicate, op_compute.input);
+        Op::ComputeOp(op_compute)
+    }
+}
+
+fn _limit_input(op: &Op) -> Option<&Op> {
+    match op {
+        Op::LimitOp(op_limit) if op_limit.limit.is_some() => Option::Some(&op_limit.input),
+        _ => Option::None,
+    }
+}
+
+/**
+ * Splits a limit into its bounds and its input.
+ */
+fn _take_limit(op: Op) -> (Option<u64>, u64, Op) {
+    match op {
+        Op::LimitOp(op_limit) => {
+            let OpLimit {
+                input,
+                limit,
+                offset,
+                ..
+            } = *op_limit;
+            (limit, offset, input)
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn _limit_over(limit: Option<u64>, offset: u64, input: Op) -> Op {
+    Op::LimitOp(Box::new(OpLimit {
+        ls: input.local_schema(),
+        vs: input.virtual_schema(),
+        input,
+        limit,
+        offset,
+    }))
+}
+
+/**
+ * Limits before projecting, a projection neither adds nor drops tuples.
+ */
+struct LimitPastProjection;
+
+impl Rule for LimitPastProjection {
+    fn name(&self) -> &'static str {
+        "limit_past_projection"
+    }
+
+    fn matches(&self, op: &Op) -> bool {
+        matches!(_limit_input(op), Some(Op::ProjectionOp(_)))
+    }
+
+    fn rewrite(&self, op: Op) -> Op {
+        let (limit, offset, input) = _take_limit(op);
+        let mut op_projection = match input {
+            Op::ProjectionOp(op_projection) => op_projection,
+            _ => unreachable!(),
+        };
+
+        op_projection.input = _limit_over(limit, offset, op_projection.input);
+        Op::ProjectionOp(op_projection)
+    }
+}
+
+/**
+ * Limits before computing expressions, so they are only computed for the tuples kept.
+ */
+struct LimitPastCompute;
+
+impl Rule for LimitPastCompute {
+    fn name(&self) -> &'static str {
+        "limit_past_compute"
+    }
+
+    fn matches(&self, op: &Op) -> bool {
+        matches!(_limit_input(op), Some(Op::ComputeOp(_)))
+    }
+
+    fn rewrite(&self, op: Op) -> Op {
+        let (limit, offset, input) = _take_limit(op);
+        let mut op_compute = match input {
+            Op::ComputeOp(op_compute) => op_compute,
+            _ => unreachable!(),
+        };
+
+        op_compute.input = _limit_over(limit, offset, op_compute.input);
+        Op::ComputeOp(op_compute)
+    }
+}
+
+/**
+ * Moves a limit into the join input whose tuples each match exactly one tuple of the other
+ * input, the join then produces exactly as many tuples as that input.
+ */
+struct LimitIntoJoin;
+
+impl Rule for LimitIntoJoin {
+    fn name(&self) -> &'static str {
+        "limit_into_join"
+    }
+
+    fn matches(&self, op: &Op) -> bool {
+        match _limit_input(op) {
+            Some(Op::JoinOp(op_join)) => _preserved_side(op_join).is_some(),
+            _ => false,
+        }
+    }
+
+    fn rewrite(&self, op: Op) -> Op {
+        let (limit, offset, input) = _take_limit(op);
+        let mut op_join = match input {
+            Op::JoinOp(op_join) => op_join,
+            _ => unreachable!(),
+        };
+
+        if _preserved_side(&op_join).unwrap() {
+            op_join.build = _limit_over(limit, offset, op_join.build);
+        } else {
+            op_join.probe = _limit_over(limit, offset, op_join.probe);
+        }
+        Op::JoinOp(op_join)
+    }
+}
+
+/**
+ * The sort and join below a limit, if the sort only reads columns of the join input whose
+ * tuples each match exactly one tuple of the other input. Gives that input, `true` for the
+ * build side.
+ */
+fn _top_n_side(op: &Op) -> Option<bool> {
+    let op_sort = match _limit_input(op) {
+        Some(Op::SortOp(op_sort)) => op_sort,
+        _ => return Option::None,
+    };
+    let op_join = match &op_sort.input {
+        Op::JoinOp(op_join) => op_join,
+        _ => return Option::None,
+    };
+
+    let is_build = _preserved_side(op_join)?;
+    let input = if is_build {
+        &op_join.build
+    } else {
+        &op_join.probe
+    };
+    let vs = input.virtual_schema();
+    if !op_sort.order_columns.iter().all(|o| vs.contains(&o.column)) {
+        return Option::None;
+    }
+
+    // Already limited by an earlier application
+    match _limit_input(input) {
+        Some(Op::SortOp(_)) => Option::None,
+        _ => Option::Some(is_build),
+    }
+}
+
+/**
+ * Copies a top-N into the join input it orders by, when that input's tuples each match exactly
+ * one tuple of the other. The first tuples of the join then come from the first tuples of that
+ * input, so only those have to be joined. The sort and limit above stay, they still order the
+ * joined tuples and apply the offset.
+ */
+struct TopNIntoJoin;
+
+impl Rule for TopNIntoJoin {
+    fn name(&self) -> &'static str {
+        "top_n_into_join"
+    }
+
+    fn matches(&self, op: &Op) -> bool {
+        _top_n_side(op).is_some()
+    }
+
+    fn rewrite(&self, op: Op) -> Op {
+        let is_build = _top_n_side(&op).unwrap();
+        let (limit, offset, input) = _take_limit(op);
+        let OpSort {
+            input,
+            order_columns,
+            ls,
+            vs,
+        } = match input {
+            Op::SortOp(op_sort) => *op_sort,
+            _ => unreachable!(),
+        };
+        let mut op_join = match input {
+            Op::JoinOp(op_join) => op_join,
+            _ => unreachable!(),
+        };
+
+        let top_n = |input: Op| {
+            let sort = Op::SortOp(Box::new(OpSort {
+                ls: input.local_schema(),
+                vs: input.virtual_schema(),
+                input,
+                order_columns: order_columns.clone(),
+            }));
+            _limit_over(limit.map(|l| l + offset), 0, sort)
+        };
+        if is_build {
+            op_join.build = top_n(op_join.build);
+        } else {
+            op_join.probe = top_n(op_join.probe);
+        }
+
+        let sort = Op::SortOp(Box::new(OpSort {
+            input: Op::JoinOp(op_join),
+            order_columns,
+            ls,
+            vs,
+        }));
+        _limit_over(limit, offset, sort)
+    }
+}
+
+/**
+ * The input of a join whose tuples each match exactly one tuple of the other input, `true`
+ * for the build side.
+ */
+fn _preserved_side(op_join: &OpJoin) -> Option<bool> {
+    if op_join.build_matches_once() {
+        Option::Some(true)
+    } else if op_join.probe_matches_once() {
+        Option::Some(false)
+    } else {
+        Option::None
+    }
+}
+
+/**
+ * The input of a join that only checks a foreign key, `true` for the probe side: the other
+ * input's tuples each match exactly once, and none of its columns are in `ls`, the columns
+ * needed above the join.
+ */
+fn _removable_side(op_join: &OpJoin, ls: &LocalSchema) -> Option<bool> {
+    let unused = |input: &Op| {
+        let vs = input.virtual_schema();
+        !ls.columns.iter().any(|c| vs.contains(c))
+    };
+
+    if unused(&op_join.probe) && op_join.build_matches_once() {
+        Option::Some(true)
+    } else if unused(&op_join.build) && op_join.probe_matches_once() {
+        Option::Some(false)
+    } else {
+        Option::None
+    }
+}
+
+/**
+ * Removes a join to a table that contributes nothing but a foreign key check the metadata
+ * already guarantees. Either input may be the removed table, the planner picks build and probe
+ * sides before the join order is chosen.
+ */
+struct JoinElimination;
+
+impl Rule for JoinElimination {
+    fn name(&self) -> &'static str {
+        "join_elimination"
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::Projected
+    }
+
+    fn matches(&self, op: &Op) -> bool {
+        match op {
+            Op::JoinOp(op_join) => match &op_join.ls {
+                Some(ls) => _removable_side(op_join, ls).is_some(),
+                None => false,
+            },
+            // The join may still produce columns only it needed, only the projection's count
+            Op::ProjectionOp(op_projection) => match (&op_projection.input, &op_projection.ls) {
+                (Op::JoinOp(op_join), Some(ls)) => _removable_side(op_join, ls).is_some(),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn rewrite(&self, op: Op) -> Op {
+        // A projection right above the join is merged into the one replacing it
+        let (op_join, ls) = match op {
+            Op::JoinOp(op_join) => {
+                let ls = op_join.ls.clone().unwrap();
+                (*op_join, ls)
+            }
+            Op::ProjectionOp(op_projection) => match op_projection.input {
+                Op::JoinOp(op_join) => (*op_join, op_projection.ls.unwrap()),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+
+        let probe_removed = _removable_side(&op_join, &ls).unwrap();
+        let kept = if probe_removed {
+            op_join.build
+        } else {
+            op_join.probe
+        };
+
+        // The join projected its output in place, the kept input still has to
+        if kept.local_schema().unwrap().columns == ls.columns {
+            return kept;
+        }
+        let kept = match kept {
+            Op::ProjectionOp(op_projection) => op_projection.input,
+            _ => kept,
+        };
+        let op = Op::ProjectionOp(Box::new(OpProjection {
+            vs: kept.virtual_schema(),
+            input: kept,
+            projection: ls.columns.clone(),
+            ls: Option::Some(ls),
+        }));
+
+        // The kept input may be a redundant join as well, removed under the same projection
+        if self.matches(&op) {
+            return self.rewrite(op);
+        }
+        op
+    }
+}
+
+/**
+ * The scan an op reads, if it reads only one and doesn't combine its tuples.
+ */
+fn _single_scan(op: &Op) -> Option<&OpScan> {
+    match op {
+        Op::ScanOp(op_scan) => Option::Some(op_scan),
+        Op::FilterOp(op_filter) => _single_scan(&op_filter.input),
+        Op::ProjectionOp(op_projection) => _single_scan(&op_projection.input),
+        Op::ComputeOp(op_compute) => _single_scan(&op_compute.input),
+        _ => Option::None,
+    }
+}
+
+/**
+ * What a partial aggregation of a join input groups by: the final grouping columns it
+ * produces, then its join keys.
+ */
+fn _partial_grouping(grouping: &[ColRef], input: &Op, keys: &[ColRef]) -> Vec<ColRef> {
+    let vs = input.virtual_schema();
+    let mut partial: Vec<ColRef> = grouping
+        .iter()
+        .filter(|c| vs.contains(c))
+        .cloned()
+        .collect();
+    for key in keys {
+        if !partial.contains(key) {
+            partial.push(key.clone());
+        }
+    }
+    partial
+}
+
+/**
+ * The join input to aggregate early, `true` for the build side: the one producing the
+ * aggregated column, unless it is aggregated already or the partial grouping includes its
+ * primary key, which would leave every tuple in a group of its own.
+ */
+fn _eager_side(op_agg: &OpAggGroup) -> Option<bool> {
+    let op_join = match &op_agg.input {
+        Op::JoinOp(op_join) if !op_agg.combines_partials => op_join,
+        _ => return Option::None,
+    };
+
+    let source = op_agg.source();
+    let (is_build, input, keys) = if op_join.build.virtual_schema().contains(&source) {
+        (true, &op_join.build, &op_join.build_join_attributes)
+    } else {
+        (false, &op_join.probe, &op_join.probe_join_attributes)
+    };
+    if let Op::AggGroupOp(_) = input {
+        return Option::None;
+    }
+
+    let grouping = _partial_grouping(&op_agg.grouping, input, keys);
+    match _single_scan(input) {
+        Some(op_scan)
+            if !op_scan.primary_key.is_empty()
+                && op_scan.primary_key.iter().all(|k| grouping.contains(k)) =>
+        {
+            Option::None
+        }
+        _ => Option::Some(is_build),
+    }
+}
+
+/**
+ * Aggregates one join input by its grouping columns and join keys before the join, so fewer
+ * tuples are joined, and combines the partial aggregates above it. All tuples of a partial
+ * group match the same tuples of the other input, so summing the partial sums or counts per
+ * final group gives the same result.
+ */
+struct EagerAggregation;
+
+impl Rule for EagerAggregation {
+    fn name(&self) -> &'static str {
+        "eager_aggregation"
+    }
+
+    fn matches(&self, op: &Op) -> bool {
+        match op {
+            Op::AggGroupOp(op_agg) => _eager_side(op_agg).is_some(),
+            _ => false,
+        }
+    }
+
+    fn rewrite(&self, op: Op) -> Op {
+        let op_agg = match op {
+            Op::AggGroupOp(op_agg) => *op_agg,
+            _ => unreachable!(),
+        };
+        let is_build = _eager_side(&op_agg).unwrap();
+        let OpAggGroup {
+            input,
+            grouping,
+            agg_field,
+            combines_partials: _,
+            ls,
+            vs,
+        } = op_agg;
+        let mut op_join = match input {
+            Op::JoinOp(op_join) => op_join,
+            _ => unreachable!(),
+        };
+
+        let partial = |input: Op, keys: &[ColRef]| {
+            let partial_grouping = _partial_grouping(&grouping, &input, keys);
+            let mut columns = partial_grouping.clone();
+            columns.push(agg_field.clone());
+            Op::AggGroupOp(Box::new(OpAggGroup {
+                input,
+                grouping: partial_grouping,
+                agg_field: agg_field.clone(),
+                combines_partials: false,
+                ls: Option::None,
+                vs: VirtualSchema::from_custom(columns),
+            }))
+        };
+        if is_build {
+            op_join.build = partial(op_join.build, &op_join.build_join_attributes);
+        } else {
+            op_join.probe = partial(op_join.probe, &op_join.probe_join_attributes);
+        }
+        op_join.vs = VirtualSchema::cat(
+            &op_join.build.virtual_schema(),
+            &op_join.probe.virtual_schema(),
+        );
+
+        Op::AggGroupOp(Box::new(OpAggGroup {
+            input: Op::JoinOp(op_join),
+            grouping,
+            agg_field,
+            combines_partials: true,
+            ls,
+            vs,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RuleSet;
+    use crate::test_util::*;
+
+    #[test]
+    fn removes_join_to_unused_primary_key_side() {
+        assert_plan(
+            "SELECT L.PRICE FROM LINEITEM L, ORDERS O WHERE L.OKEY = O.OKEY",
+            &[
+                "Projection ([L.PRICE])",
+                "Scan (table=LINEITEM, file=lineitem.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn removes_chain_of_foreign_key_joins() {
+        assert_plan(
+            "SELECT L.PRICE FROM LINEITEM L, ORDERS O, CUST C \
+             WHERE L.OKEY = O.OKEY AND O.CKEY = C.CKEY",
+            &[
+                "Projection ([L.PRICE])",
+                "Scan (table=LINEITEM, file=lineitem.tbl)",
+            ],
+        );
+        assert_plan(
+            "SELECT L.PRICE, O.CKEY FROM LINEITEM L, ORDERS O, CUST C \
+             WHERE L.OKEY = O.OKEY AND O.CKEY = C.CKEY",
+            &[
+                "Projection ([L.PRICE, O.CKEY])",
+                "Join (O.OKEY == L.OKEY)",
+                "(build)>Projection ([O.OKEY, O.CKEY])",
+                "        Scan (table=ORDERS, file=orders.tbl)",
+                "(probe)>Projection ([L.OKEY, L.PRICE])",
+                "        Scan (table=LINEITEM, file=lineitem.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn keeps_joins_that_filter() {
+        // A filtered ORDERS may miss some LINEITEM orders, LI2 has no primary key to match once
+        assert_plan(
+            "SELECT L.PRICE FROM LINEITEM L, ORDERS O WHERE L.OKEY = O.OKEY AND O.ZIP = 3",
+            &[
+                "Join (L.OKEY == O.OKEY)",
+                "(build)>Projection ([L.OKEY, L.PRICE])",
+                "        Scan (table=LINEITEM, file=lineitem.tbl)",
+                "(probe)>Projection ([O.OKEY])",
+                "        Filter (O.ZIP == 3)",
+                "        Projection ([O.OKEY, O.ZIP])",
+                "        Scan (table=ORDERS, file=orders.tbl)",
+            ],
+        );
+        assert_plan(
+            "SELECT L.PRICE FROM LINEITEM L, LI2 M WHERE L.OKEY = M.OKEY",
+            &[
+                "Join (L.OKEY == M.OKEY)",
+                "(build)>Projection ([L.OKEY, L.PRICE])",
+                "        Scan (table=LINEITEM, file=lineitem.tbl)",
+                "(probe)>Projection ([M.OKEY])",
+                "        Scan (table=LI2, file=li2.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn keeps_join_if_disabled() {
+        let op = plan_with(
+            "SELECT L.PRICE FROM LINEITEM L, ORDERS O WHERE L.OKEY = O.OKEY",
+            &RuleSet::without(&["join_elimination"]),
+        );
+        assert!(op.to_string().starts_with("Join (L.OKEY == O.OKEY)"));
+    }
+
+    #[test]
+    fn aggregates_join_input_early() {
+        assert_plan(
+            "SELECT O.ZIP, SUM(L.PRICE) FROM LINEITEM L, ORDERS O \
+             WHERE L.OKEY = O.OKEY GROUP BY O.ZIP",
+            &[
+                "AggGroup (grouping=[O.ZIP], agg_field=Sum(L.PRICE), combining partials)",
+                "Join (L.OKEY == O.OKEY)",
+                "(build)>AggGroup (grouping=[L.OKEY], agg_field=Sum(L.PRICE))",
+                "        Projection ([L.OKEY, L.PRICE])",
+                "        Scan (table=LINEITEM, file=lineitem.tbl)",
+                "(probe)>Projection ([O.OKEY, O.ZIP])",
+                "        Scan (table=ORDERS, file=orders.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn skips_early_aggregation_by_primary_key() {
+        // Every ORDERS tuple would be a group of its own
+        assert_plan(
+            "SELECT L.FLAG, COUNT(O.ZIP) FROM LINEITEM L, ORDERS O \
+             WHERE L.OKEY = O.OKEY GROUP BY L.FLAG",
+            &[
+                "AggGroup (grouping=[L.FLAG], agg_field=Count(O.ZIP))",
+                "Join (L.OKEY == O.OKEY)",
+                "(build)>Projection ([L.OKEY, L.FLAG])",
+                "        Scan (table=LINEITEM, file=lineitem.tbl)",
+                "(probe)>Projection ([O.OKEY, O.ZIP])",
+                "        Scan (table=ORDERS, file=orders.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn pushes_limit_past_compute() {
+        assert_plan(
+            "SELECT L.PRICE, L.QTY + 1 FROM LINEITEM L LIMIT 5",
+            &[
+                "Projection ([L.PRICE, (L.QTY + 1)])",
+                "Compute ([(L.QTY + 1)])",
+                "Limit (limit=5, offset=0)",
+                "Projection ([L.PRICE, L.QTY])",
+                "Scan (table=LINEITEM, file=lineitem.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn pushes_limit_into_foreign_key_side_of_join() {
+        assert_plan(
+            "SELECT L.PRICE, O.ZIP FROM LINEITEM L, ORDERS O WHERE L.OKEY = O.OKEY LIMIT 5",
+            &[
+                "Join (L.OKEY == O.OKEY)",
+                "(build)>Limit (limit=5, offset=0)",
+                "        Projection ([L.OKEY, L.PRICE])",
+                "        Scan (table=LINEITEM, file=lineitem.tbl)",
+                "(probe)>Projection ([O.OKEY, O.ZIP])",
+                "        Scan (table=ORDERS, file=orders.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn copies_top_n_into_join_input_it_orders_by() {
+        assert_plan(
+            "SELECT L.PRICE, O.ZIP FROM LINEITEM L, ORDERS O \
+             WHERE L.OKEY = O.OKEY ORDER BY L.PRICE LIMIT 5",
+            &[
+                "Limit (limit=5, offset=0)",
+                "Sort (order_by=[L.PRICE ASC NULLS LAST])",
+                "Join (L.OKEY == O.OKEY)",
+                "(build)>Projection ([L.OKEY, L.PRICE])",
+                "        Limit (limit=5, offset=0)",
+                "        Sort (order_by=[L.PRICE ASC NULLS LAST])",
+                "        Projection ([L.PRICE, L.OKEY])",
+                "        Scan (table=LINEITEM, file=lineitem.tbl)",
+                "(probe)>Projection ([O.OKEY, O.ZIP])",
+                "        Scan (table=ORDERS, file=orders.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn keeps_top_n_ordered_by_primary_key_side() {
+        // An order matches any number of lines, its first tuples don't give the first results
+        assert_plan(
+            "SELECT L.PRICE, O.ZIP FROM LINEITEM L, ORDERS O \
+             WHERE L.OKEY = O.OKEY ORDER BY O.ZIP LIMIT 5",
+            &[
+                "Projection ([L.PRICE, O.ZIP])",
+                "Limit (limit=5, offset=0)",
+                "Sort (order_by=[O.ZIP ASC NULLS LAST])",
+                "Join (L.OKEY == O.OKEY)",
+                "(build)>Projection ([L.OKEY, L.PRICE])",
+                "        Scan (table=LINEITEM, file=lineitem.tbl)",
+                "(probe)>Projection ([O.OKEY, O.ZIP])",
+                "        Scan (table=ORDERS, file=orders.tbl)",
+            ],
+        );
+    }
+}
@@ -1,9 +1,11 @@
+use std::cmp::Ordering;
 use std::collections::HashSet;
 
 use sqlparser::ast::*;
 
-use crate::ops::{ColRef, Op, OpFilter};
+use crate::ops::{ColRef, FilterPredicate, Literal, Op, OpEmpty, OpFilter};
 use crate::planner::EqualityFactSet;
+use crate::projection::{convert_expr, convert_literal};
 
 #[derive(Debug)]
 pub enum Selection {
@@ -15,7 +17,250 @@ pub enum Selection {
     Gt(Box<Selection>, Box<Selection>),
     LtEq(Box<Selection>, Box<Selection>),
     GtEq(Box<Selection>, Box<Selection>),
-    Const(String),
+    Const(Literal),
+    Like {
+        field: ColRef,
+        pattern: String,
+        negated: bool,
+        case_insensitive: bool,
+    },
+    Null,
+    IsNull(Box<Selection>),
+    IsNotNull(Box<Selection>),
+    Truth(Truth),
+}
+
+/**
+ * Outcome of a predicate under SQL's three-valued logic.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Truth {
+    True,
+    False,
+    Unknown,
+}
+
+fn _compose_comparison(cmp: &str, l: Selection, r: Selection) -> Selection {
+    let (l, r) = (Box::new(l), Box::new(r));
+    match cmp {
+        "==" => Selection::Eq(l, r),
+        "!=" => Selection::NotEq(l, r),
+        "<" => Selection::Lt(l, r),
+        ">" => Selection::Gt(l, r),
+        "<=" => Selection::LtEq(l, r),
+        ">=" => Selection::GtEq(l, r),
+        _ => unreachable!(),
+    }
+}
+
+fn _column_comparison(colref: &ColRef, cmp: &str, value: Literal) -> Selection {
+    _compose_comparison(
+        cmp,
+        Selection::Identity(colref.clone()),
+        Selection::Const(value),
+    )
+}
+
+/**
+ * The comparison that holds after swapping its sides.
+ */
+fn _flip(cmp: &'static str) -> &'static str {
+    match cmp {
+        "<" => ">",
+        ">" => "<",
+        "<=" => ">=",
+        ">=" => "<=",
+        _ => cmp,
+    }
+}
+
+fn _holds(cmp: &str, ordering: Ordering) -> bool {
+    match cmp {
+        "==" => ordering == Ordering::Equal,
+        "!=" => ordering != Ordering::Equal,
+        "<" => ordering == Ordering::Less,
+        ">" => ordering == Ordering::Greater,
+        "<=" => ordering != Ordering::Greater,
+        ">=" => ordering != Ordering::Less,
+        _ => unreachable!(),
+    }
+}
+
+/**
+ * Merges the `column op constant` conjuncts on each column, e.g. `x > 5 AND x > 7` into `x > 7`.
+ * Returns `None` if they contradict each other. Other conjuncts are kept as they are.
+ */
+fn _merge_ranges(conjuncts: Vec<Selection>) -> Option<Vec<Selection>> {
+    let mut columns: Vec<(ColRef, Vec<(&'static str, Literal)>)> = vec![];
+    let mut others = vec![];
+
+    for conjunct in conjuncts {
+        match conjunct._into_comparison() {
+            Ok((cmp, Selection::Identity(colref), Selection::Const(value))) => {
+                match columns.iter_mut().find(|(c, _)| c == &colref) {
+                    Some((_, bounds)) => bounds.push((cmp, value)),
+                    None => columns.push((colref, vec![(cmp, value)])),
+                }
+            }
+            Ok((cmp, l, r)) => others.push(_compose_comparison(cmp, l, r)),
+            Err(other) => others.push(other),
+        }
+    }
+
+    let mut merged = vec![];
+    for (colref, bounds) in columns {
+        merged.extend(_merge_column(&colref, bounds)?);
+    }
+    merged.extend(others);
+    Option::Some(merged)
+}
+
+fn _merge_column(colref: &ColRef, bounds: Vec<(&'static str, Literal)>) -> Option<Vec<Selection>> {
+    // Constants of different kinds are left for the type checker to complain about
+    let comparable = bounds
+        .iter()
+        .all(|(_, a)| bounds.iter().all(|(_, b)| a.compare(b).is_some()));
+    if !comparable {
+        return Option::Some(
+            bounds
+                .into_iter()
+                .map(|(cmp, value)| _column_comparison(colref, cmp, value))
+                .collect(),
+        );
+    }
+
+    let mut lower: Option<(Literal, bool)> = Option::None;
+    let mut upper: Option<(Literal, bool)> = Option::None;
+    let mut eq: Option<Literal> = Option::None;
+    let mut not_eq: Vec<Literal> = vec![];
+
+    for (cmp, value) in bounds {
+        match cmp {
+            "==" => {
+                if let Some(existing) = &eq {
+                    if existing.compare(&value) != Some(Ordering::Equal) {
+                        return Option::None;
+                    }
+                }
+                eq = Option::Some(value);
+            }
+            "!=" => not_eq.push(value),
+            ">" | ">=" => {
+                let inclusive = cmp == ">=";
+                let tighter = match &lower {
+                    None => true,
+                    Some((current, current_inclusive)) => match value.compare(current).unwrap() {
+                        Ordering::Greater => true,
+                        Ordering::Equal => *current_inclusive && !inclusive,
+                        Ordering::Less => false,
+                    },
+                };
+                if tighter {
+                    lower = Option::Some((value, inclusive));
+                }
+            }
+            "<" | "<=" => {
+                let inclusive = cmp == "<=";
+                let tighter = match &upper {
+                    None => true,
+                    Some((current, current_inclusive)) => match value.compare(current).unwrap() {
+                        Ordering::Less => true,
+                        Ordering::Equal => *current_inclusive && !inclusive,
+                        Ordering::Greater => false,
+                    },
+                };
+                if tighter {
+                    upper = Option::Some((value, inclusive));
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    if let (Some((low, low_inclusive)), Some((high, high_inclusive))) = (&lower, &upper) {
+        match low.compare(high).unwrap() {
+            Ordering::Greater => return Option::None,
+            Ordering::Equal if !(*low_inclusive && *high_inclusive) => return Option::None,
+            // `x >= 5 AND x <= 5` pins the value
+            Ordering::Equal if eq.is_none() => eq = Option::Some(low.clone()),
+            _ => {}
+        }
+    }
+
+    let within = |value: &Literal| {
+        let above = match &lower {
+            Some((low, inclusive)) => match value.compare(low).unwrap() {
+                Ordering::Greater => true,
+                Ordering::Equal => *inclusive,
+                Ordering::Less => false,
+            },
+            None => true,
+        };
+        let below = match &upper {
+            Some((high, inclusive)) => match value.compare(high).unwrap() {
+                Ordering::Less => true,
+                Ordering::Equal => *inclusive,
+                Ordering::Greater => false,
+            },
+            None => true,
+        };
+        above && below
+    };
+
+    if let Some(eq) = eq {
+        let excluded = not_eq
+            .iter()
+            .any(|n| n.compare(&eq) == Some(Ordering::Equal));
+        if !within(&eq) || excluded {
+            return Option::None;
+        }
+        return Option::Some(vec![_column_comparison(colref, "==", eq)]);
+    }
+
+    let mut out = vec![];
+    if let Some((low, inclusive)) = lower.clone() {
+        out.push(_column_comparison(
+            colref,
+            if inclusive { ">=" } else { ">" },
+            low,
+        ));
+    }
+    if let Some((high, inclusive)) = upper.clone() {
+        out.push(_column_comparison(
+            colref,
+            if inclusive { "<=" } else { "<" },
+            high,
+        ));
+    }
+
+    let mut kept_not_eq: Vec<Literal> = vec![];
+    for value in not_eq {
+        // Values outside the range are already excluded
+        let duplicate = kept_not_eq
+            .iter()
+            .any(|k| k.compare(&value) == Some(Ordering::Equal));
+        if within(&value) && !duplicate {
+            kept_not_eq.push(value);
+        }
+    }
+    out.extend(
+        kept_not_eq
+            .into_iter()
+            .map(|value| _column_comparison(colref, "!=", value)),
+    );
+
+    Option::Some(out)
+}
+
+fn _is_column_of(selection: &Selection, alias: &str) -> bool {
+    match selection {
+        Selection::Identity(colref) => _is_table_column(colref, alias),
+        _ => false,
+    }
+}
+
+fn _is_table_column(colref: &ColRef, alias: &str) -> bool {
+    matches!(colref, ColRef::TableRef { table, column: _ } if table == alias)
 }
 
 impl Selection {
@@ -37,14 +282,227 @@ impl Selection {
         }
     }
 
+    /**
+     * Rewrites the selection into an equivalent, simpler one before any filter is planned,
+     * following SQL's three-valued logic:
+     * - comparisons between constants are folded, and comparisons with NULL are unknown
+     * - `IS [NOT] NULL` on a column that `op` never produces NULLs for is decided outright
+     * - comparisons of a column with itself are decided, or become `IS NOT NULL`
+     * - the constant bounds on each column are merged into the tightest range, an equality or a
+     *   contradiction
+     *
+     * A conjunction that can never be true becomes `Truth(False)` or `Truth(Unknown)`.
+     * Expects a normalized selection.
+     */
+    pub fn simplified(self, op: &Op) -> Selection {
+        let (cmp, l, r) = match self._into_comparison() {
+            Ok(comparison) => comparison,
+            Err(Selection::And(subselections)) => {
+                let mut kept = vec![];
+                let mut unknown = false;
+                for subselection in subselections {
+                    match subselection.simplified(op) {
+                        Selection::Truth(Truth::True) => {}
+                        Selection::Truth(Truth::False) => return Selection::Truth(Truth::False),
+                        Selection::Truth(Truth::Unknown) => unknown = true,
+                        other => kept.push(other),
+                    }
+                }
+
+                if unknown {
+                    // Unknown AND anything is never true, which WHERE treats as false
+                    return Selection::Truth(Truth::Unknown);
+                }
+                return match _merge_ranges(kept) {
+                    Some(kept) => Selection::And(kept),
+                    None => Selection::Truth(Truth::False),
+                };
+            }
+            Err(Selection::IsNull(inner)) => {
+                return match *inner {
+                    Selection::Null => Selection::Truth(Truth::True),
+                    Selection::Const(_) => Selection::Truth(Truth::False),
+                    Selection::Identity(colref) if !op.column_nullable(&colref) => {
+                        Selection::Truth(Truth::False)
+                    }
+                    inner => Selection::IsNull(Box::new(inner)),
+                }
+            }
+            Err(Selection::IsNotNull(inner)) => {
+                return match *inner {
+                    Selection::Null => Selection::Truth(Truth::False),
+                    Selection::Const(_) => Selection::Truth(Truth::True),
+                    Selection::Identity(colref) if !op.column_nullable(&colref) => {
+                        Selection::Truth(Truth::True)
+                    }
+                    inner => Selection::IsNotNull(Box::new(inner)),
+                }
+            }
+            Err(other) => return other,
+        };
+
+        match (l, r) {
+            (Selection::Null, _) | (_, Selection::Null) => Selection::Truth(Truth::Unknown),
+            (Selection::Const(a), Selection::Const(b)) => match a.compare(&b) {
+                Some(ordering) => Selection::Truth(if _holds(cmp, ordering) {
+                    Truth::True
+                } else {
+                    Truth::False
+                }),
+                None => _compose_comparison(cmp, Selection::Const(a), Selection::Const(b)),
+            },
+            // Keep the column on the left so bounds can be merged
+            (Selection::Const(value), Selection::Identity(colref)) => _compose_comparison(
+                _flip(cmp),
+                Selection::Identity(colref),
+                Selection::Const(value),
+            ),
+            (Selection::Identity(a), Selection::Identity(b)) if a == b => {
+                if !_holds(cmp, Ordering::Equal) {
+                    // Also never true for NULL, so false as far as WHERE is concerned
+                    Selection::Truth(Truth::False)
+                } else if op.column_nullable(&a) {
+                    Selection::IsNotNull(Box::new(Selection::Identity(a)))
+                } else {
+                    Selection::Truth(Truth::True)
+                }
+            }
+            (l, r) => _compose_comparison(cmp, l, r),
+        }
+    }
+
+    /**
+     * Copies each filter on a join column to the columns it is joined with, e.g. `l.OKEY < 100`
+     * with `l.OKEY = o.OKEY` also gives `o.OKEY < 100`, so both join inputs can be filtered.
+     * Expects a simplified selection, with columns on the left of comparisons.
+     */
+    pub fn with_transitive_predicates(self, equalities: &EqualityFactSet) -> Selection {
+        let conjuncts = match self {
+            Selection::And(conjuncts) => conjuncts,
+            _ => return self,
+        };
+
+        let mut inferred = vec![];
+        for conjunct in &conjuncts {
+            if let Some((cmp, Selection::Identity(colref), Selection::Const(value))) =
+                conjunct._comparison_parts()
+            {
+                for other in equalities.equivalents(colref) {
+                    inferred.push(_column_comparison(&other, cmp, value.clone()));
+                }
+            } else if let Selection::Like {
+                field,
+                pattern,
+                negated,
+                case_insensitive,
+            } = conjunct
+            {
+                for other in equalities.equivalents(field) {
+                    inferred.push(Selection::Like {
+                        field: other,
+                        pattern: pattern.clone(),
+                        negated: *negated,
+                        case_insensitive: *case_insensitive,
+                    });
+                }
+            }
+        }
+
+        let mut conjuncts = conjuncts;
+        conjuncts.extend(inferred);
+        Selection::And(conjuncts)
+    }
+
+    fn _comparison_parts(&self) -> Option<(&'static str, &Selection, &Selection)> {
+        match self {
+            Selection::Eq(l, r) => Option::Some(("==", l, r)),
+            Selection::NotEq(l, r) => Option::Some(("!=", l, r)),
+            Selection::Lt(l, r) => Option::Some(("<", l, r)),
+            Selection::Gt(l, r) => Option::Some((">", l, r)),
+            Selection::LtEq(l, r) => Option::Some(("<=", l, r)),
+            Selection::GtEq(l, r) => Option::Some((">=", l, r)),
+            _ => Option::None,
+        }
+    }
+
+    /**
+     * Splits a binary comparison into its filter op and sides, or gives the selection back.
+     */
+    fn _into_comparison(self) -> Result<(&'static str, Selection, Selection), Selection> {
+        let cmp = match self._comparison_parts() {
+            Some((cmp, _, _)) => cmp,
+            None => return Err(self),
+        };
+
+        match self {
+            Selection::Eq(l, r)
+            | Selection::NotEq(l, r)
+            | Selection::Lt(l, r)
+            | Selection::Gt(l, r)
+            | Selection::LtEq(l, r)
+            | Selection::GtEq(l, r) => Ok((cmp, *l, *r)),
+            _ => unreachable!(),
+        }
+    }
+
+    /**
+     * Whether some conjunct can only be true when the columns of `alias` are not NULL, which is
+     * what allows an outer join that pads `alias` with NULLs to be planned as an inner join.
+     * Expects a normalized selection.
+     */
+    pub fn rejects_nulls_from(&self, alias: &str) -> bool {
+        let selections = match self {
+            Selection::And(selections) => selections,
+            _ => unreachable!(),
+        };
+
+        selections.iter().any(|selection| match selection {
+            Selection::Eq(l, r)
+            | Selection::NotEq(l, r)
+            | Selection::Lt(l, r)
+            | Selection::Gt(l, r)
+            | Selection::LtEq(l, r)
+            | Selection::GtEq(l, r) => _is_column_of(l, alias) || _is_column_of(r, alias),
+            Selection::Like { field, .. } => _is_table_column(field, alias),
+            Selection::IsNotNull(inner) => _is_column_of(inner, alias),
+            _ => false,
+        })
+    }
+
+    /**
+     * Expressions that filters are applied to, e.g. the `l.PRICE * l.QTY` in
+     * `l.PRICE * l.QTY > 100`, which have to be computed before filtering.
+     */
+    pub fn computed_fields(&self) -> Vec<ColRef> {
+        match self {
+            Selection::Identity(colref @ ColRef::ComputedRef { .. }) => vec![colref.clone()],
+            Selection::And(selections) => selections
+                .iter()
+                .flat_map(|s| s.computed_fields())
+                .collect(),
+            Selection::Eq(l, r)
+            | Selection::NotEq(l, r)
+            | Selection::Lt(l, r)
+            | Selection::Gt(l, r)
+            | Selection::LtEq(l, r)
+            | Selection::GtEq(l, r) => {
+                let mut fields = l.computed_fields();
+                fields.extend(r.computed_fields());
+                fields
+            }
+            Selection::IsNull(inner) | Selection::IsNotNull(inner) => inner.computed_fields(),
+            _ => vec![],
+        }
+    }
+
     pub fn potential_equijoins(&self) -> HashSet<(ColRef, ColRef)> {
         let mut out = HashSet::new();
 
         if let Selection::And(selections) = self {
             for selection in selections {
                 if let Selection::Eq(l, r) = selection {
-                    if let Selection::Identity(lref) = l.as_ref() {
-                        if let Selection::Identity(rref) = r.as_ref() {
+                    if let Selection::Identity(lref @ ColRef::TableRef { .. }) = l.as_ref() {
+                        if let Selection::Identity(rref @ ColRef::TableRef { .. }) = r.as_ref() {
                             out.insert((lref.clone(), rref.clone()));
                         }
                     }
@@ -59,23 +517,68 @@ impl Selection {
     }
 
     pub fn apply_filter_ops(&self, mut op: Op, existing_equalities: &EqualityFactSet) -> Op {
-        fn _compose_binary_filter(bin_op: &str, op: Op, colref: &ColRef, rep: &str) -> Op {
+        fn _compose_filter(op: Op, colref: &ColRef, predicate: FilterPredicate) -> Op {
             let vs = op.virtual_schema();
             let ls = op.local_schema();
             Op::FilterOp(Box::new(OpFilter {
                 field: colref.clone(),
                 input: op,
-                op: bin_op.to_string(),
-                value: rep.to_string(),
+                predicate,
                 ls,
                 vs,
-                cfg_name: Option::None,
             }))
         }
 
+        fn _compose_binary_filter(bin_op: &str, op: Op, colref: &ColRef, rep: &Literal) -> Op {
+            let predicate = FilterPredicate::Compare {
+                op: bin_op.to_string(),
+                value: rep.clone(),
+            };
+            _compose_filter(op, colref, predicate)
+        }
+
+        fn _compose_column_filter(bin_op: &str, op: Op, colref: &ColRef, other: &ColRef) -> Op {
+            let predicate = FilterPredicate::CompareColumns {
+                op: bin_op.to_string(),
+                other: other.clone(),
+            };
+            _compose_filter(op, colref, predicate)
+        }
+
+        fn _compose_empty(op: Op) -> Op {
+            let vs = op.virtual_schema();
+            let ls = op.local_schema();
+            Op::EmptyOp(Box::new(OpEmpty { input: op, ls, vs }))
+        }
+
         match self {
             Selection::Identity(_) => panic!("Identity filter not supported"),
+            Selection::Null => panic!("NULL filter not supported"),
+            Selection::Truth(Truth::True) => op,
+            Selection::Truth(_) => _compose_empty(op),
+            Selection::IsNull(inner) => match inner.as_ref() {
+                Selection::Identity(colref) => {
+                    _compose_filter(op, colref, FilterPredicate::IsNull { negated: false })
+                }
+                _ => panic!("IS NULL must be applied to a column"),
+            },
+            Selection::IsNotNull(inner) => match inner.as_ref() {
+                Selection::Identity(colref) => {
+                    _compose_filter(op, colref, FilterPredicate::IsNull { negated: true })
+                }
+                _ => panic!("IS NOT NULL must be applied to a column"),
+            },
             Selection::Const(_) => panic!("Const filter not supported"),
+            Selection::Like {
+                field,
+                pattern,
+                negated,
+                case_insensitive,
+            } => _compose_filter(
+                op,
+                field,
+                FilterPredicate::from_like(pattern, *negated, *case_insensitive),
+            ),
             Selection::And(subfilters) => {
                 for subfilter in subfilters {
                     op = subfilter.apply_filter_ops(op, existing_equalities);
@@ -94,7 +597,8 @@ impl Selection {
                             // Nothing to do!
                             op
                         } else {
-                            panic!("Can't apply new identity-identity equality")
+                            // A join edge left out of the join tree, checked after joining
+                            _compose_column_filter("==", op, colref1, colref2)
                         }
                     }
                     _ => panic!("Unknown equality configuration."),
@@ -105,6 +609,9 @@ impl Selection {
                 | (Selection::Const(rep), Selection::Identity(colref)) => {
                     _compose_binary_filter("!=", op, colref, rep)
                 }
+                (Selection::Identity(colref1), Selection::Identity(colref2)) => {
+                    _compose_column_filter("!=", op, colref1, colref2)
+                }
                 _ => panic!("Unknown inequality configuration."),
             },
             Selection::Lt(l, r) => match (l.as_ref(), r.as_ref()) {
@@ -114,6 +621,9 @@ impl Selection {
                 (Selection::Const(rep), Selection::Identity(colref)) => {
                     _compose_binary_filter(">", op, colref, rep)
                 }
+                (Selection::Identity(colref1), Selection::Identity(colref2)) => {
+                    _compose_column_filter("<", op, colref1, colref2)
+                }
                 _ => panic!("Unknown less-than configuration."),
             },
             Selection::Gt(l, r) => match (l.as_ref(), r.as_ref()) {
@@ -123,6 +633,9 @@ impl Selection {
                 (Selection::Const(rep), Selection::Identity(colref)) => {
                     _compose_binary_filter("<", op, colref, rep)
                 }
+                (Selection::Identity(colref1), Selection::Identity(colref2)) => {
+                    _compose_column_filter(">", op, colref1, colref2)
+                }
                 _ => panic!("Unknown greater-than configuration."),
             },
             Selection::LtEq(l, r) => match (l.as_ref(), r.as_ref()) {
@@ -132,6 +645,9 @@ impl Selection {
                 (Selection::Const(rep), Selection::Identity(colref)) => {
                     _compose_binary_filter(">=", op, colref, rep)
                 }
+                (Selection::Identity(colref1), Selection::Identity(colref2)) => {
+                    _compose_column_filter("<=", op, colref1, colref2)
+                }
                 _ => panic!("Unknown less-than-equal configuration."),
             },
             Selection::GtEq(l, r) => match (l.as_ref(), r.as_ref()) {
@@ -141,12 +657,62 @@ impl Selection {
                 (Selection::Const(rep), Selection::Identity(colref)) => {
                     _compose_binary_filter("<=", op, colref, rep)
                 }
+                (Selection::Identity(colref1), Selection::Identity(colref2)) => {
+                    _compose_column_filter(">=", op, colref1, colref2)
+                }
                 _ => panic!("Unknown greater-than-equal configuration."),
             },
         }
     }
 }
 
+/**
+ * The parser has no `ILIKE`, so a case-insensitive match is written as `LOWER(x) LIKE 'abc%'` or
+ * `UPPER(x) LIKE 'ABC%'`.
+ */
+fn _convert_like(left: &Expr, right: &Expr, negated: bool) -> Selection {
+    let pattern = match right {
+        Expr::Value(Value::SingleQuotedString(pattern)) => pattern.clone(),
+        _ => panic!("LIKE pattern must be a string constant"),
+    };
+
+    let (field, case_insensitive, never_matches) = match left {
+        Expr::CompoundIdentifier(idents) => (idents.as_slice().into(), false, false),
+        Expr::Function(function) if function.args.len() == 1 => {
+            let name = function.name.to_string().to_lowercase();
+            let folded = match name.as_str() {
+                "lower" => pattern.to_lowercase(),
+                "upper" => pattern.to_uppercase(),
+                _ => panic!("Unsupported function {} in LIKE", name),
+            };
+
+            match &function.args[0] {
+                FunctionArg::Unnamed(Expr::CompoundIdentifier(idents)) => {
+                    (idents.as_slice().into(), true, folded != pattern)
+                }
+                _ => panic!("LIKE must be applied to a column"),
+            }
+        }
+        _ => panic!("LIKE must be applied to a column"),
+    };
+
+    // A folded value never contains the other case, e.g. LOWER(x) LIKE 'Ab%'
+    if never_matches {
+        return if negated {
+            Selection::IsNotNull(Box::new(Selection::Identity(field)))
+        } else {
+            Selection::Truth(Truth::False)
+        };
+    }
+
+    Selection::Like {
+        field,
+        pattern,
+        negated,
+        case_insensitive,
+    }
+}
+
 impl From<&sqlparser::ast::Expr> for Selection {
     fn from(expr: &Expr) -> Selection {
         match expr {
@@ -178,15 +744,162 @@ impl From<&sqlparser::ast::Expr> for Selection {
                     Box::new(left.as_ref().into()),
                     Box::new(right.as_ref().into()),
                 ),
+                BinaryOperator::Plus
+                | BinaryOperator::Minus
+                | BinaryOperator::Multiply
+                | BinaryOperator::Divide
+                | BinaryOperator::Modulus => Selection::Identity(convert_expr(expr)),
+                BinaryOperator::Like => _convert_like(left, right, false),
+                BinaryOperator::NotLike => _convert_like(left, right, true),
                 _ => panic!("Unsupported binary op"),
             },
             Expr::CompoundIdentifier(idents) => Selection::Identity(idents.as_slice().into()),
             Expr::Nested(subexpr) => subexpr.as_ref().into(),
-            Expr::Value(v) => match v {
-                Value::Number(val_str, _) => Selection::Const(val_str.to_string()),
-                _ => panic!("Unsupported value type"),
-            },
+            Expr::Case { .. } | Expr::Cast { .. } => Selection::Identity(convert_expr(expr)),
+            Expr::IsNull(subexpr) => Selection::IsNull(Box::new(subexpr.as_ref().into())),
+            Expr::IsNotNull(subexpr) => Selection::IsNotNull(Box::new(subexpr.as_ref().into())),
+            Expr::Value(Value::Null) => Selection::Null,
+            Expr::Value(Value::Boolean(true)) => Selection::Truth(Truth::True),
+            Expr::Value(Value::Boolean(false)) => Selection::Truth(Truth::False),
+            Expr::Value(_) | Expr::TypedString { .. } => Selection::Const(convert_literal(expr)),
             _ => panic!("Unsupported selection type: {:?}", expr),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::*;
+
+    #[test]
+    fn lowers_prefix_and_substring_patterns() {
+        assert_plan(
+            "SELECT L.OKEY FROM LINEITEM L WHERE L.COMMENT LIKE 'ab%'",
+            &[
+                "Projection ([L.OKEY])",
+                "Filter (L.COMMENT Prefix 'ab')",
+                "Projection ([L.OKEY, L.COMMENT])",
+                "Scan (table=LINEITEM, file=lineitem.tbl)",
+            ],
+        );
+        assert_plan(
+            "SELECT L.OKEY FROM LINEITEM L WHERE L.COMMENT LIKE '%ab%'",
+            &[
+                "Projection ([L.OKEY])",
+                "Filter (L.COMMENT Contains 'ab')",
+                "Projection ([L.OKEY, L.COMMENT])",
+                "Scan (table=LINEITEM, file=lineitem.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn lowers_other_patterns_to_regex() {
+        assert_plan(
+            "SELECT L.OKEY FROM LINEITEM L WHERE L.COMMENT NOT LIKE 'a_c%'",
+            &[
+                "Projection ([L.OKEY])",
+                "Filter (L.COMMENT NOT Regex '^a.c.*$')",
+                "Projection ([L.OKEY, L.COMMENT])",
+                "Scan (table=LINEITEM, file=lineitem.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn matches_case_folded_column_case_insensitively() {
+        assert_plan(
+            "SELECT L.OKEY FROM LINEITEM L WHERE UPPER(L.COMMENT) LIKE 'AB%'",
+            &[
+                "Projection ([L.OKEY])",
+                "Filter (L.COMMENT Prefix (ci) 'AB')",
+                "Projection ([L.OKEY, L.COMMENT])",
+                "Scan (table=LINEITEM, file=lineitem.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn folds_case_folded_pattern_that_never_matches() {
+        assert_plan(
+            "SELECT L.OKEY FROM LINEITEM L WHERE LOWER(L.COMMENT) LIKE 'Ab%'",
+            &["Empty"],
+        );
+        assert_plan(
+            "SELECT L.OKEY FROM LINEITEM L WHERE UPPER(L.COMMENT) LIKE '%ab%'",
+            &["Empty"],
+        );
+        assert_plan(
+            "SELECT O.OKEY FROM ORDERS O WHERE LOWER(O.PRIORITY) NOT LIKE 'Ab%'",
+            &[
+                "Projection ([O.OKEY])",
+                "Filter (O.PRIORITY IS NOT NULL)",
+                "Projection ([O.OKEY, O.PRIORITY])",
+                "Scan (table=ORDERS, file=orders.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn merges_bounds_on_one_column() {
+        assert_plan(
+            "SELECT L.OKEY FROM LINEITEM L WHERE L.QTY > 3 AND L.QTY < 10 AND L.QTY >= 5",
+            &[
+                "Projection ([L.OKEY])",
+                "Filter (L.QTY < 10)",
+                "Filter (L.QTY >= 5)",
+                "Projection ([L.OKEY, L.QTY])",
+                "Scan (table=LINEITEM, file=lineitem.tbl)",
+            ],
+        );
+        assert_plan(
+            "SELECT L.OKEY FROM LINEITEM L WHERE L.QTY >= 5 AND L.QTY <= 5",
+            &[
+                "Projection ([L.OKEY])",
+                "Filter (L.QTY == 5)",
+                "Projection ([L.OKEY, L.QTY])",
+                "Scan (table=LINEITEM, file=lineitem.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn empties_contradicting_bounds() {
+        for sql in &[
+            "SELECT L.OKEY FROM LINEITEM L WHERE L.QTY > 10 AND L.QTY < 3",
+            "SELECT L.OKEY FROM LINEITEM L WHERE L.QTY = 5 AND L.QTY = 6",
+        ] {
+            assert_plan(sql, &["Empty"]);
+        }
+    }
+
+    #[test]
+    fn copies_bounds_across_join_keys() {
+        assert_plan(
+            "SELECT L.OKEY FROM LINEITEM L, ORDERS O, LI2 M \
+             WHERE L.OKEY = O.OKEY AND M.OKEY = O.OKEY AND L.OKEY > 100",
+            &[
+                "Join (L.OKEY == O.OKEY)",
+                "(build)>Filter (L.OKEY > 100)",
+                "        Projection ([L.OKEY])",
+                "        Scan (table=LINEITEM, file=lineitem.tbl)",
+                "(probe)>Join (O.OKEY == M.OKEY)",
+                "        (build)>Filter (O.OKEY > 100)",
+                "                Projection ([O.OKEY])",
+                "                Scan (table=ORDERS, file=orders.tbl)",
+                "        (probe)>Filter (M.OKEY > 100)",
+                "                Projection ([M.OKEY])",
+                "                Scan (table=LI2, file=li2.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn empties_bounds_contradicting_across_join_keys() {
+        assert_plan(
+            "SELECT L.OKEY FROM LINEITEM L, ORDERS O \
+             WHERE L.OKEY = O.OKEY AND L.OKEY > 100 AND O.OKEY < 50",
+            &["Empty"],
+        );
+    }
+}
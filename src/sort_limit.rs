@@ -1,73 +1,184 @@
 use sqlparser::ast::*;
 
-use crate::ops::{ColRef, Op, OpSortLimit, Order};
-use crate::projection::convert_expr;
+use crate::ops::{ColRef, Op, OpLimit, OpSort, Order, OrderColumn};
+use crate::projection::{convert_expr, Projection};
 
 #[derive(Debug)]
 pub struct SortLimit {
-    order_columns: Vec<ColRef>,
-    order: Option<Order>,
-    limit: u64,
+    order_columns: Vec<OrderColumn>,
+    limit: Option<u64>,
+    offset: u64,
 }
 
 impl SortLimit {
-    pub fn apply_sort_limit_ops(&self, op: Op) -> Op {
-        if self.limit == i32::MAX as u64 && self.order_columns.is_empty() {
-            return op;
+    pub fn apply_sort_limit_ops(&self, mut op: Op) -> Op {
+        if !self.order_columns.is_empty() {
+            let vs = op.virtual_schema();
+
+            op = Op::SortOp(Box::new(OpSort {
+                input: op,
+                order_columns: self.order_columns.clone(),
+                ls: Option::None,
+                vs,
+            }));
         }
 
-        let vs = op.virtual_schema();
-
-        Op::SortLimitOp(Box::new(OpSortLimit {
-            input: op,
-            order_columns: self.order_columns.clone(),
-            order: self.order.clone(),
-            limit: self.limit,
-            ls: Option::None,
-            vs,
-            cfg_name: None,
-        }))
+        if self.limit.is_some() || self.offset > 0 {
+            let vs = op.virtual_schema();
+
+            op = Op::LimitOp(Box::new(OpLimit {
+                input: op,
+                limit: self.limit,
+                offset: self.offset,
+                ls: Option::None,
+                vs,
+            }));
+        }
+
+        op
     }
 }
 
-fn _get_limit(expr: &Expr) -> u64 {
+fn _get_count(expr: &Expr, clause: &str) -> u64 {
     match expr {
         Expr::Value(value) => match value {
             Value::Number(string, _) => string.parse::<u64>().unwrap(),
-            _ => panic!("Limit value must be a number"),
+            _ => panic!("{} value must be a number", clause),
         },
-        _ => panic!("Limit expr must be a value"),
+        _ => panic!("{} expr must be a value", clause),
     }
 }
 
-impl From<&Query> for SortLimit {
-    fn from(query: &Query) -> SortLimit {
-        let mut order = Option::None;
-        let mut order_columns = Vec::new();
-        let mut limit = i32::MAX as u64;
-
-        for order_expr in &query.order_by {
-            order_columns.push(convert_expr(&order_expr.expr));
-
-            // We can only order either ASC or DESC, so pick the first one used
-            if order.is_none() {
-                if let Some(asc) = order_expr.asc {
-                    order = match asc {
-                        true => Option::Some(Order::ASC),
-                        false => Option::Some(Order::DESC),
-                    }
+/**
+ * ORDER BY items may name an output alias or a 1-based output position before falling back to
+ * table columns. An alias given to different columns is ambiguous.
+ */
+fn _resolve_order_expr(expr: &Expr, projection: &Projection) -> ColRef {
+    match expr {
+        Expr::Identifier(ident) => {
+            let mut matches: Vec<&ColRef> = Vec::new();
+            for (alias, item) in projection.aliases.iter().zip(&projection.items) {
+                if alias == &ident.value && !matches.contains(&item) {
+                    matches.push(item);
                 }
             }
+
+            // Wildcards name their columns without the table alias, so duplicates are common
+            match matches.as_slice() {
+                [] => {}
+                [item] => return (*item).clone(),
+                _ => panic!(
+                    "ORDER BY {} is ambiguous between {}",
+                    ident.value,
+                    matches
+                        .iter()
+                        .map(|item| item.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ),
+            }
+        }
+        Expr::Value(Value::Number(position, _)) => {
+            let position = position
+                .parse::<usize>()
+                .unwrap_or_else(|_| panic!("Invalid ORDER BY position {}", position));
+            if position == 0 || position > projection.items.len() {
+                panic!("ORDER BY position {} is not in select list", position);
+            }
+            return projection.items[position - 1].clone();
         }
+        _ => {}
+    }
 
-        if let Some(limit_expr) = &query.limit {
-            limit = _get_limit(limit_expr);
+    convert_expr(expr)
+}
+
+impl SortLimit {
+    pub fn from_query(query: &Query, projection: &Projection) -> SortLimit {
+        let mut order_columns = Vec::new();
+
+        for order_expr in &query.order_by {
+            let order = match order_expr.asc {
+                Some(false) => Order::DESC,
+                _ => Order::ASC,
+            };
+            let nulls_first = order_expr
+                .nulls_first
+                .unwrap_or_else(|| order.default_nulls_first());
+
+            order_columns.push(OrderColumn {
+                column: _resolve_order_expr(&order_expr.expr, projection),
+                order,
+                nulls_first,
+            });
         }
 
+        let limit = query.limit.as_ref().map(|e| _get_count(e, "Limit"));
+        let offset = query
+            .offset
+            .as_ref()
+            .map(|o| _get_count(&o.value, "Offset"))
+            .unwrap_or(0);
+
         SortLimit {
-            order,
             order_columns,
             limit,
+            offset,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::*;
+
+    #[test]
+    fn orders_by_output_alias() {
+        assert_plan(
+            "SELECT L.PRICE AS P FROM LINEITEM L ORDER BY P DESC",
+            &[
+                "Sort (order_by=[L.PRICE DESC NULLS FIRST])",
+                "Projection ([L.PRICE])",
+                "Scan (table=LINEITEM, file=lineitem.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn orders_by_output_position() {
+        assert_plan(
+            "SELECT L.PRICE, L.QTY FROM LINEITEM L ORDER BY 2 DESC",
+            &[
+                "Projection ([L.PRICE, L.QTY])",
+                "Sort (order_by=[L.QTY DESC NULLS FIRST])",
+                "Projection ([L.QTY, L.PRICE])",
+                "Scan (table=LINEITEM, file=lineitem.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn alias_of_one_column_twice_is_not_ambiguous() {
+        assert_plan(
+            "SELECT L.OKEY, L.OKEY FROM LINEITEM L ORDER BY OKEY",
+            &[
+                "Projection ([L.OKEY, L.OKEY])",
+                "Sort (order_by=[L.OKEY ASC NULLS LAST])",
+                "Projection ([L.OKEY])",
+                "Scan (table=LINEITEM, file=lineitem.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ORDER BY OKEY is ambiguous between L.OKEY, O.OKEY")]
+    fn rejects_alias_of_different_columns() {
+        plan_sql("SELECT * FROM LINEITEM L, ORDERS O WHERE L.OKEY = O.OKEY ORDER BY OKEY");
+    }
+
+    #[test]
+    #[should_panic(expected = "ORDER BY position 3 is not in select list")]
+    fn rejects_position_past_select_list() {
+        plan_sql("SELECT L.PRICE, L.QTY FROM LINEITEM L ORDER BY 3");
+    }
+}
@@ -0,0 +1,237 @@
+use crate::metadata::{MetaColumnStats, Metadata};
+use crate::ops::*;
+
+/**
+ * Rows assumed for a table without statistics.
+ */
+const DEFAULT_TABLE_ROWS: f64 = 1000.0;
+
+// Classic System R guesses for when statistics are missing
+const DEFAULT_EQ_SELECTIVITY: f64 = 0.1;
+const DEFAULT_RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+const DEFAULT_MATCH_SELECTIVITY: f64 = 0.1;
+const DEFAULT_GROUP_FRACTION: f64 = 0.1;
+const DEFAULT_NULL_SELECTIVITY: f64 = 0.1;
+
+fn _column_stats<'a>(op: &Op, colref: &ColRef, meta: &'a Metadata) -> Option<&'a MetaColumnStats> {
+    match op {
+        Op::ScanOp(op) => match colref {
+            ColRef::TableRef { table: _, column } => meta
+                .tables
+                .iter()
+                .rfind(|t| t.name == op.tab_name)?
+                .stats
+                .as_ref()?
+                .columns
+                .get(column),
+            _ => Option::None,
+        },
+        Op::JoinOp(op) => {
+            if op.build.virtual_schema().contains(colref) {
+                _column_stats(&op.build, colref, meta)
+            } else {
+                _column_stats(&op.probe, colref, meta)
+            }
+        }
+        Op::CrossJoinOp(op) => {
+            if op.build.virtual_schema().contains(colref) {
+                _column_stats(&op.build, colref, meta)
+            } else {
+                _column_stats(&op.probe, colref, meta)
+            }
+        }
+        Op::AggGroupOp(op) => match colref {
+            ColRef::AggregateRef { .. } => Option::None,
+            _ => _column_stats(&op.input, colref, meta),
+        },
+        Op::ComputeOp(op) => {
+            if op.exprs.contains(colref) {
+                Option::None
+            } else {
+                _column_stats(&op.input, colref, meta)
+            }
+        }
+        Op::SubqueryProjOp(op) => {
+            let idx = op.vs.columns.iter().position(|c| c == colref)?;
+            let inner = &op.input.local_schema()?.columns[idx];
+            _column_stats(&op.input, inner, meta)
+        }
+        Op::FilterOp(op) => _column_stats(&op.input, colref, meta),
+        Op::ProjectionOp(op) => _column_stats(&op.input, colref, meta),
+        Op::SortOp(op) => _column_stats(&op.input, colref, meta),
+        Op::LimitOp(op) => _column_stats(&op.input, colref, meta),
+        Op::EmptyOp(op) => _column_stats(&op.input, colref, meta),
+    }
+}
+
+fn _distinct_values(op: &Op, colref: &ColRef, meta: &Metadata) -> Option<f64> {
+    _column_stats(op, colref, meta)?.distinct.map(|d| d as f64)
+}
+
+fn _date_position(value: &str) -> Option<f64> {
+    Literal::parse_date(value)?;
+    let parts: Vec<f64> = value
+        .split('-')
+        .map(|p| p.parse::<f64>().unwrap())
+        .collect();
+    // Close enough to a day count for interpolating
+    Option::Some(parts[0] * 372.0 + parts[1] * 31.0 + parts[2])
+}
+
+/**
+ * Maps a value onto a line so ranges can be interpolated between the column bounds. Strings use
+ * their leading bytes.
+ */
+fn _positions(values: &[&str]) -> Vec<f64> {
+    let numeric: Option<Vec<f64>> = values.iter().map(|v| v.parse::<f64>().ok()).collect();
+    if let Some(numeric) = numeric {
+        return numeric;
+    }
+
+    let dates: Option<Vec<f64>> = values.iter().map(|v| _date_position(v)).collect();
+    if let Some(dates) = dates {
+        return dates;
+    }
+
+    values
+        .iter()
+        .map(|v| {
+            v.bytes()
+                .take(8)
+                .enumerate()
+                .map(|(i, b)| b as f64 / 256f64.powi(i as i32 + 1))
+                .sum()
+        })
+        .collect()
+}
+
+pub fn filter_selectivity(predicate: &FilterPredicate, stats: Option<&MetaColumnStats>) -> f64 {
+    let eq_selectivity = stats
+        .and_then(|s| s.distinct)
+        .map(|d| 1.0 / (d.max(1) as f64))
+        .unwrap_or(DEFAULT_EQ_SELECTIVITY);
+
+    match predicate {
+        FilterPredicate::Compare { op, value: _ } if op == "==" => return eq_selectivity,
+        FilterPredicate::Compare { op, value: _ } if op == "!=" => return 1.0 - eq_selectivity,
+        FilterPredicate::CompareColumns { op, other: _ } if op == "==" => {
+            return DEFAULT_EQ_SELECTIVITY
+        }
+        FilterPredicate::CompareColumns { op, other: _ } if op == "!=" => {
+            return 1.0 - DEFAULT_EQ_SELECTIVITY
+        }
+        FilterPredicate::CompareColumns { .. } => return DEFAULT_RANGE_SELECTIVITY,
+        FilterPredicate::IsNull { negated: false } => return DEFAULT_NULL_SELECTIVITY,
+        FilterPredicate::IsNull { negated: true } => return 1.0 - DEFAULT_NULL_SELECTIVITY,
+        _ => {}
+    }
+
+    let (lower, upper) = match predicate.as_range() {
+        Some(range) => range,
+        None => {
+            return match predicate {
+                FilterPredicate::Match { negated: true, .. } => 1.0 - DEFAULT_MATCH_SELECTIVITY,
+                _ => DEFAULT_MATCH_SELECTIVITY,
+            }
+        }
+    };
+
+    let (min, max) = match stats {
+        Some(MetaColumnStats {
+            min: Some(min),
+            max: Some(max),
+            distinct: _,
+        }) => (min, max),
+        _ => return DEFAULT_RANGE_SELECTIVITY,
+    };
+
+    let lower = lower.as_ref().map(|l| l.raw()).unwrap_or(min);
+    let upper = upper.as_ref().map(|u| u.raw()).unwrap_or(max);
+    let positions = _positions(&[min, max, lower, upper]);
+    let (min, max, lower, upper) = (positions[0], positions[1], positions[2], positions[3]);
+
+    if max <= min {
+        return eq_selectivity;
+    }
+
+    ((upper.min(max) - lower.max(min)) / (max - min)).clamp(0.0, 1.0)
+}
+
+/**
+ * Estimated number of tuples the op produces.
+ */
+pub fn estimate_rows(op: &Op, meta: &Metadata) -> f64 {
+    match op {
+        Op::ScanOp(op) => meta
+            .tables
+            .iter()
+            .rfind(|t| t.name == op.tab_name)
+            .and_then(|t| t.stats.as_ref())
+            .map(|s| s.rows as f64)
+            .unwrap_or(DEFAULT_TABLE_ROWS),
+        Op::FilterOp(op) => {
+            let stats = _column_stats(&op.input, &op.field, meta);
+            estimate_rows(&op.input, meta) * filter_selectivity(&op.predicate, stats)
+        }
+        // Each tuple of a foreign key side matches exactly one tuple of the primary key side
+        Op::JoinOp(op) if op.build_matches_once() => estimate_rows(&op.build, meta),
+        Op::JoinOp(op) if op.probe_matches_once() => estimate_rows(&op.probe, meta),
+        Op::JoinOp(op) => {
+            let build = estimate_rows(&op.build, meta);
+            let probe = estimate_rows(&op.probe, meta);
+            // Keys are assumed independent, but can't be more selective than a unique key
+            let divisor: f64 = op
+                .build_join_attributes
+                .iter()
+                .zip(&op.probe_join_attributes)
+                .map(|(b, p)| {
+                    let build_distinct = _distinct_values(&op.build, b, meta).unwrap_or(build);
+                    let probe_distinct = _distinct_values(&op.probe, p, meta).unwrap_or(probe);
+                    build_distinct.max(probe_distinct).max(1.0)
+                })
+                .product();
+
+            build * probe / divisor.min(build.max(probe)).max(1.0)
+        }
+        Op::CrossJoinOp(op) => estimate_rows(&op.build, meta) * estimate_rows(&op.probe, meta),
+        Op::AggGroupOp(op) => {
+            let input = estimate_rows(&op.input, meta);
+            let groups: Option<f64> = op
+                .grouping
+                .iter()
+                .map(|g| _distinct_values(&op.input, g, meta))
+                .product();
+
+            groups.unwrap_or(input * DEFAULT_GROUP_FRACTION).min(input)
+        }
+        Op::LimitOp(op) => {
+            let input = (estimate_rows(&op.input, meta) - op.offset as f64).max(0.0);
+            match op.limit {
+                Some(limit) => input.min(limit as f64),
+                None => input,
+            }
+        }
+        Op::ProjectionOp(op) => estimate_rows(&op.input, meta),
+        Op::SortOp(op) => estimate_rows(&op.input, meta),
+        Op::ComputeOp(op) => estimate_rows(&op.input, meta),
+        Op::SubqueryProjOp(op) => estimate_rows(&op.input, meta),
+        Op::EmptyOp(_) => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::RuleSet;
+    use crate::test_util::*;
+
+    #[test]
+    fn foreign_key_join_keeps_foreign_key_side_rows() {
+        let op = plan_with(
+            "SELECT L.PRICE FROM LINEITEM L, ORDERS O, CUST C \
+             WHERE L.OKEY = O.OKEY AND O.CKEY = C.CKEY",
+            &RuleSet::without(&["join_elimination"]),
+        );
+        assert_eq!(estimate_rows(&op, &metadata()), 6000000.0);
+    }
+}
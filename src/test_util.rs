@@ -0,0 +1,93 @@
+use sqlparser::ast::*;
+use sqlparser::dialect::GenericDialect;
+use sqlparser::parser::Parser;
+
+use crate::metadata::Metadata;
+use crate::ops::Op;
+use crate::planner::plan;
+use crate::rules::RuleSet;
+use crate::trace::Trace;
+
+/**
+ * A small TPC-H like schema: every `LINEITEM` and `LI2` tuple references one `ORDERS` tuple,
+ * which references one `CUST` tuple.
+ */
+pub const META: &str = r#"{
+    "path": "data/",
+    "buffsize": 1048576,
+    "tables": [
+        {
+            "name": "LINEITEM",
+            "file": "lineitem.tbl",
+            "filetype": "text",
+            "schema": {
+                "columns": [
+                    ["OKEY", "LONG"], ["QTY", "INT"], ["PRICE", "DEC"], ["SHIPDATE", "DATE"],
+                    ["FLAG", {"CHAR": 1}], ["COMMENT", {"VARCHAR": 44}]
+                ]
+            },
+            "stats": {"rows": 6000000, "columns": {"OKEY": {"distinct": 1500000}}},
+            "foreign_keys": [{"columns": ["OKEY"], "references": "ORDERS"}]
+        },
+        {
+            "name": "ORDERS",
+            "file": "orders.tbl",
+            "filetype": "text",
+            "schema": {
+                "columns": [
+                    ["OKEY", "LONG"], ["CKEY", "LONG"], ["ZIP", "LONG"],
+                    ["PRIORITY", {"CHAR": 15}]
+                ],
+                "nullable": ["ZIP", "PRIORITY"]
+            },
+            "stats": {"rows": 1500000, "columns": {"OKEY": {"distinct": 1500000}}},
+            "primary_key": ["OKEY"],
+            "foreign_keys": [{"columns": ["CKEY"], "references": "CUST"}]
+        },
+        {
+            "name": "CUST",
+            "file": "cust.tbl",
+            "filetype": "text",
+            "schema": {"columns": [["CKEY", "LONG"], ["NAME", {"VARCHAR": 25}]]},
+            "stats": {"rows": 150000, "columns": {"CKEY": {"distinct": 150000}}},
+            "primary_key": ["CKEY"]
+        },
+        {
+            "name": "LI2",
+            "file": "li2.tbl",
+            "filetype": "text",
+            "schema": {"columns": [["OKEY", "LONG"], ["QTY", "INT"]]}
+        }
+    ]
+}"#;
+
+pub fn metadata() -> Metadata {
+    serde_json::from_str(META).unwrap()
+}
+
+/**
+ * The logical plan of `sql` over `META`, as `memo::optimize` would receive it.
+ */
+pub fn plan_with(sql: &str, rules: &RuleSet) -> Op {
+    let meta = metadata();
+    let query = match Parser::parse_sql(&GenericDialect {}, sql)
+        .unwrap()
+        .remove(0)
+    {
+        Statement::Query(q) => q,
+        _ => panic!("Not a query"),
+    };
+    let mut trace = Trace::new(false, &meta);
+    plan(&query, &meta, rules, &mut trace).0
+}
+
+pub fn plan_sql(sql: &str) -> Op {
+    plan_with(sql, &RuleSet::without(&[]))
+}
+
+/**
+ * Checks the logical plan of `sql`, one line of its display per entry.
+ */
+pub fn assert_plan(sql: &str, expected: &[&str]) {
+    assert_eq!(plan_sql(sql).to_string(), expected.join("\n"));
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+
+use json::{object, JsonValue};
+
+use crate::metadata::Metadata;
+use crate::ops::*;
+use crate::stats;
+
+/**
+ * One transformation the optimizer applied, with the subtree before and after it.
+ */
+#[derive(Debug)]
+pub struct TraceStep {
+    /**
+     * `rules`, `local_project` or `join_enumeration`.
+     */
+    pub phase: &'static str,
+    pub rule: String,
+    pub before: String,
+    pub after: String,
+    pub cost_before: Option<f64>,
+    pub cost_after: Option<f64>,
+}
+
+/**
+ * Log of the steps the optimizer takes, only collected when tracing is enabled. Logical
+ * rewrites are costed by the tuples all ops of the subtree produce, join enumeration by the
+ * cost model.
+ */
+pub struct Trace<'a> {
+    enabled: bool,
+    meta: &'a Metadata,
+    steps: Vec<TraceStep>,
+}
+
+fn _produced_rows(op: &Op, meta: &Metadata) -> f64 {
+    stats::estimate_rows(op, meta)
+        + op.inputs()
+            .into_iter()
+            .map(|input| _produced_rows(input, meta))
+            .sum::<f64>()
+}
+
+impl<'a> Trace<'a> {
+    pub fn new(enabled: bool, meta: &'a Metadata) -> Trace<'a> {
+        Trace {
+            enabled,
+            meta,
+            steps: Vec::new(),
+        }
+    }
+
+    /**
+     * Callers check this before building anything they only need for a step.
+     */
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn record(&mut self, step: TraceStep) {
+        if self.enabled {
+            self.steps.push(step);
+        }
+    }
+
+    pub fn record_rewrite(&mut self, phase: &'static str, rule: &str, before: &Op, after: &Op) {
+        if !self.enabled {
+            return;
+        }
+        self.steps.push(TraceStep {
+            phase,
+            rule: rule.to_string(),
+            before: format!("{}", before),
+            after: format!("{}", after),
+            cost_before: Option::Some(_produced_rows(before, self.meta)),
+            cost_after: Option::Some(_produced_rows(after, self.meta)),
+        });
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        JsonValue::Array(
+            self.steps
+                .iter()
+                .map(|step| {
+                    object! {
+                        phase: step.phase,
+                        rule: step.rule.clone(),
+                        before: step.before.clone(),
+                        after: step.after.clone(),
+                        cost_before: step.cost_before,
+                        cost_after: step.cost_after,
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+impl fmt::Display for Trace<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, step) in self.steps.iter().enumerate() {
+            write!(f, "#{} {}: {}", idx + 1, step.phase, step.rule)?;
+            if let (Some(before), Some(after)) = (step.cost_before, step.cost_after) {
+                write!(
+                    f,
+                    " (cost {:.0} -> {:.0}, {:+.0})",
+                    before,
+                    after,
+                    after - before
+                )?;
+            }
+            write!(
+                f,
+                "\nbefore> {}\nafter>  {}\n",
+                indent_str(&step.before),
+                indent_str(&step.after)
+            )?;
+        }
+        Ok(())
+    }
+}
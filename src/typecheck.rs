@@ -0,0 +1,307 @@
+use crate::metadata::MetaType;
+use crate::ops::*;
+
+/**
+ * Validates the types of every filter, aggregate and computed expression in the plan, coercing
+ * filter constants to the type of the column they are compared against. Filters the types
+ * decide are dropped or make their input empty. All mismatches are reported together.
+ */
+pub fn check_types(op: Op) -> Op {
+    let mut errors = Vec::new();
+    let op = _check_op(op, &mut errors);
+
+    if !errors.is_empty() {
+        panic!("Type errors:\n{}", errors.join("\n"));
+    }
+
+    op
+}
+
+fn _check_op(op: Op, errors: &mut Vec<String>) -> Op {
+    // Subqueries were already checked when they were planned
+    let op = match op {
+        Op::SubqueryProjOp(_) => op,
+        _ => op.map_inputs(|input| _check_op(input, errors)),
+    };
+
+    match op {
+        Op::FilterOp(mut op) => {
+            // A column that doesn't type check was reported where it is computed
+            let column_type = match op.input.try_column_type(&op.field) {
+                Ok(column_type) => column_type,
+                Err(_) => return Op::FilterOp(op),
+            };
+            match &op.predicate {
+                FilterPredicate::Compare { op: cmp, value } => {
+                    match coerce_literal(cmp, value, column_type) {
+                        Ok(Coerced::Compare(cmp, value)) => {
+                            op.predicate = FilterPredicate::Compare { op: cmp, value };
+                        }
+                        // The comparison still never matches NULL
+                        Ok(Coerced::Always(true)) if op.input.column_nullable(&op.field) => {
+                            op.predicate = FilterPredicate::IsNull { negated: true };
+                        }
+                        Ok(Coerced::Always(true)) => return op.input,
+                        Ok(Coerced::Always(false)) => {
+                            return Op::EmptyOp(Box::new(OpEmpty {
+                                input: op.input,
+                                ls: op.ls,
+                                vs: op.vs,
+                            }))
+                        }
+                        Err(e) => errors.push(format!("  {} {}: {}", op.field, op.predicate, e)),
+                    }
+                }
+                FilterPredicate::Match { .. } => {
+                    if !column_type.is_text() {
+                        errors.push(format!(
+                            "  {} {}: can't match patterns against {:?} column",
+                            op.field, op.predicate, column_type
+                        ));
+                    }
+                }
+                FilterPredicate::CompareColumns { op: _, other } => {
+                    if let Ok(other_type) = op.input.try_column_type(other) {
+                        if column_type.common_with(&other_type).is_none() {
+                            errors.push(format!(
+                                "  {} {}: can't compare {:?} with {:?}",
+                                op.field, op.predicate, column_type, other_type
+                            ));
+                        }
+                    }
+                }
+                FilterPredicate::IsNull { .. } => {}
+            }
+            Op::FilterOp(op)
+        }
+        Op::AggGroupOp(op) => {
+            if let ColRef::AggregateRef { func, source } = &op.agg_field {
+                // A source that doesn't type check was reported where it is computed
+                if let Ok(source_type) = op.input.try_column_type(source) {
+                    if let Err(e) = func.output_type(source_type) {
+                        errors.push(format!("  {}: {}", op.agg_field, e));
+                    }
+                }
+            }
+            Op::AggGroupOp(op)
+        }
+        Op::ComputeOp(op) => {
+            for col in &op.exprs {
+                if let ColRef::ComputedRef { expr } = col {
+                    if let Err(e) = expr.result_type(&op.input) {
+                        errors.push(format!("  {}", e));
+                    }
+                }
+            }
+            Op::ComputeOp(op)
+        }
+        _ => op,
+    }
+}
+
+fn _integer_range(column_type: MetaType) -> (i128, i128) {
+    match column_type {
+        MetaType::INT => (i32::MIN as i128, i32::MAX as i128),
+        _ => (i64::MIN as i128, i64::MAX as i128),
+    }
+}
+
+/**
+ * `column <cmp> value` for an integer column, decided outright if `value` is out of its range.
+ */
+fn _compare_integer(cmp: &str, value: i128, column_type: MetaType) -> Coerced {
+    let (min, max) = _integer_range(column_type);
+    if value < min || value > max {
+        let below = value < min;
+        return Coerced::Always(match cmp {
+            "==" => false,
+            "!=" => true,
+            "<" | "<=" => !below,
+            _ => below,
+        });
+    }
+    Coerced::Compare(cmp.to_string(), Literal::Integer(value.to_string()))
+}
+
+/**
+ * A filter constant coerced to the type of its column. Some comparisons are decided by the
+ * types alone, e.g. an `INT` column is never equal to `3.5`.
+ */
+#[derive(Debug)]
+pub enum Coerced {
+    Compare(String, Literal),
+    Always(bool),
+}
+
+/**
+ * Rewrites `column <cmp> literal` so that the literal has the column's type, possibly changing
+ * the comparison (e.g. `INT < 3.5` becomes `INT <= 3`). Only fails if the types can't be
+ * compared at all.
+ */
+pub fn coerce_literal(
+    cmp: &str,
+    literal: &Literal,
+    column_type: MetaType,
+) -> Result<Coerced, String> {
+    let mismatch = || {
+        Err(format!(
+            "can't compare {:?} column with {}",
+            column_type, literal
+        ))
+    };
+    let unchanged = || Ok(Coerced::Compare(cmp.to_string(), literal.clone()));
+
+    match (literal, column_type) {
+        (Literal::Integer(value), MetaType::DEC) => Ok(Coerced::Compare(
+            cmp.to_string(),
+            Literal::Decimal(value.clone()),
+        )),
+        (Literal::Integer(value), MetaType::INT) | (Literal::Integer(value), MetaType::LONG) => {
+            // Too long for i128 still decides the comparison, the cast saturates
+            let value = match value.parse::<i128>() {
+                Ok(value) => value,
+                Err(_) => value
+                    .parse::<f64>()
+                    .map_err(|_| format!("invalid number {}", value))?
+                    as i128,
+            };
+            Ok(_compare_integer(cmp, value, column_type))
+        }
+        (Literal::Decimal(_), MetaType::DEC) => unchanged(),
+        (Literal::Decimal(value), MetaType::INT) | (Literal::Decimal(value), MetaType::LONG) => {
+            let parsed = value
+                .parse::<f64>()
+                .map_err(|_| format!("invalid number {}", value))?;
+
+            if parsed.fract() == 0.0 {
+                return Ok(_compare_integer(cmp, parsed as i128, column_type));
+            }
+
+            Ok(match cmp {
+                "<" | "<=" => _compare_integer("<=", parsed.floor() as i128, column_type),
+                ">" | ">=" => _compare_integer(">=", parsed.ceil() as i128, column_type),
+                // An integer is never equal to a fractional number
+                _ => Coerced::Always(cmp == "!="),
+            })
+        }
+        (Literal::Text(value), column_type) if column_type.is_text() => {
+            if (cmp == "==" || cmp == "!=") && value.len() as u64 > column_type.width() {
+                // Nor is a string equal to one longer than it can be
+                return Ok(Coerced::Always(cmp == "!="));
+            }
+            unchanged()
+        }
+        (Literal::Text(value), MetaType::DATE) => match Literal::parse_date(value) {
+            Some(date) => Ok(Coerced::Compare(cmp.to_string(), date)),
+            None => mismatch(),
+        },
+        (Literal::Date(_), MetaType::DATE) => unchanged(),
+        _ => mismatch(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::*;
+
+    #[test]
+    fn rounds_fractional_bound_of_integer_column() {
+        assert_plan(
+            "SELECT L.OKEY FROM LINEITEM L WHERE L.QTY < 3.5",
+            &[
+                "Projection ([L.OKEY])",
+                "Filter (L.QTY <= 3)",
+                "Projection ([L.OKEY, L.QTY])",
+                "Scan (table=LINEITEM, file=lineitem.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn coerces_date_string() {
+        assert_plan(
+            "SELECT L.OKEY FROM LINEITEM L WHERE L.SHIPDATE >= '1995-01-01'",
+            &[
+                "Projection ([L.OKEY])",
+                "Filter (L.SHIPDATE >= DATE '1995-01-01')",
+                "Projection ([L.OKEY, L.SHIPDATE])",
+                "Scan (table=LINEITEM, file=lineitem.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    fn empties_comparisons_the_types_decide() {
+        for sql in &[
+            "SELECT L.OKEY FROM LINEITEM L WHERE L.QTY = 3.5",
+            "SELECT L.OKEY FROM LINEITEM L WHERE L.QTY > 3000000000",
+            "SELECT O.OKEY FROM ORDERS O WHERE O.PRIORITY = 'longer than fifteen'",
+        ] {
+            assert_plan(sql, &["Empty"]);
+        }
+    }
+
+    #[test]
+    fn keeps_null_check_of_always_true_comparison() {
+        assert_plan(
+            "SELECT O.OKEY FROM ORDERS O WHERE O.ZIP != 2.5",
+            &[
+                "Projection ([O.OKEY])",
+                "Filter (O.ZIP IS NOT NULL)",
+                "Projection ([O.OKEY, O.ZIP])",
+                "Scan (table=ORDERS, file=orders.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Type errors:\n  \
+        (L.COMMENT + 1): can't apply + to VARCHAR(44) and LONG\n  \
+        L.QTY == 'x': can't compare INT column with 'x'")]
+    fn reports_all_errors_together() {
+        plan_sql("SELECT L.OKEY FROM LINEITEM L WHERE L.COMMENT + 1 > 3 AND L.QTY = 'x'");
+    }
+
+    #[test]
+    #[should_panic(expected = "Type errors:\n  \
+        Sum(L.COMMENT): can't sum values of type VARCHAR(44)\n  \
+        (L.COMMENT + 1): can't apply + to VARCHAR(44) and LONG")]
+    fn reports_errors_in_aggregates_and_projections() {
+        plan_sql("SELECT L.COMMENT + 1, SUM(L.COMMENT) FROM LINEITEM L GROUP BY L.COMMENT");
+    }
+
+    #[test]
+    fn types_case_without_null_results() {
+        assert_plan(
+            "SELECT CASE WHEN L.QTY > 3 THEN L.PRICE ELSE NULL END, CAST(L.QTY AS DEC) \
+             FROM LINEITEM L",
+            &[
+                "Projection ([CASE WHEN L.QTY > 3 THEN L.PRICE END, CAST(L.QTY AS DEC)])",
+                "Compute ([CASE WHEN L.QTY > 3 THEN L.PRICE END, CAST(L.QTY AS DEC)])",
+                "Projection ([L.QTY, L.PRICE])",
+                "Scan (table=LINEITEM, file=lineitem.tbl)",
+            ],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Type errors:\n  \
+        CASE WHEN L.QTY > 3 THEN NULL END: needs a result that isn't NULL")]
+    fn reports_aggregated_case_of_only_nulls() {
+        plan_sql(
+            "SELECT L.OKEY, SUM(CASE WHEN L.QTY > 3 THEN NULL ELSE NULL END) \
+             FROM LINEITEM L GROUP BY L.OKEY",
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Type errors:\n  \
+        CAST(L.SHIPDATE AS INT): can't cast DATE to INT\n  \
+        CASE WHEN L.QTY > 3 THEN L.PRICE ELSE 'x' END: results of types DEC and CHAR(1) don't mix")]
+    fn reports_case_and_cast_errors() {
+        plan_sql(
+            "SELECT CAST(L.SHIPDATE AS INT), CASE WHEN L.QTY > 3 THEN L.PRICE ELSE 'x' END \
+             FROM LINEITEM L",
+        );
+    }
+}